@@ -0,0 +1,183 @@
+use crate::{
+    admin::dto::{
+        MaintenanceDtoRequest, MaintenanceDtoResponse, ReindexDtoResponse, WipeUsersDtoRequest,
+    },
+    shared::{
+        auth::admin::AdminAuth,
+        config::settings::Settings,
+        dto::response::{
+            ErrorCode, http_bad_request, http_error, http_forbidden, http_internal_server_error,
+            http_not_found, http_ok, http_render,
+        },
+        middleware::{maintenance::MaintenanceMode, timeout::timeout},
+    },
+    users::{
+        dto::DeletedCountDtoResponse,
+        users_service,
+        users_store::{StoreScope, UserStore},
+    },
+};
+use actix_web::{HttpResponse, delete, get, http::StatusCode, middleware::from_fn, post, web};
+use handlebars::Handlebars;
+use log::{error, warn};
+use mongodb::bson::{Bson, oid::ObjectId};
+
+/// Admin operations always target the configured database, never the `X-Test-Db` one.
+fn config_scope(config: &Settings) -> StoreScope {
+    StoreScope {
+        database: config.database.clone(),
+        comment: None,
+    }
+}
+
+/// REST API controller for administrative operations.
+///
+/// All routes are prefixed with `/admin` as specified in main.rs via `web::scope("/admin")`
+/// and require `Authorization: Bearer <ADMIN_TOKEN>`.
+///
+/// # Routes
+/// - `GET /admin/config` - Effective runtime configuration, secrets redacted
+/// - `POST /admin/reindex` - Create missing indexes (requires `ENABLE_DESTRUCTIVE_OPS`)
+/// - `POST /admin/maintenance` - Turn maintenance mode on or off
+/// - `GET /admin/users/{id}/raw` - Stored user document as relaxed Extended JSON, including
+///   fields the `User` model does not map
+/// - `POST /admin/render/{template}` - Render a registered template with the JSON body as
+///   context, answers the HTML or the render error (requires `DEV_MODE`, 404 without the
+///   web UI)
+/// - `DELETE /admin/users` - Wipe the users collection (requires `ENABLE_DESTRUCTIVE_OPS`
+///   and the confirmation token)
+
+#[get("config", wrap = "from_fn(timeout)")]
+async fn effective_config(_admin: AdminAuth, config: web::Data<Settings>) -> HttpResponse {
+    http_ok(config.redacted())
+}
+
+#[post("reindex", wrap = "from_fn(timeout)")]
+async fn reindex(
+    _admin: AdminAuth,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+) -> HttpResponse {
+    if !config.destructive_ops {
+        return http_forbidden("Destructive operations are disabled".into());
+    }
+
+    match users_service::ensure_indexes(store.get_ref(), &config_scope(&config)).await {
+        Ok(report) => http_ok(ReindexDtoResponse {
+            collection: config.users_collection.clone(),
+            created: report.created,
+            existing: report.existing,
+        }),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Reindex failed".into())
+        }
+    }
+}
+
+#[post("maintenance", wrap = "from_fn(timeout)")]
+async fn set_maintenance(
+    _admin: AdminAuth,
+    mode: web::Data<MaintenanceMode>,
+    dto: web::Json<MaintenanceDtoRequest>,
+) -> HttpResponse {
+    mode.set_enabled(dto.enabled);
+    warn!(
+        "Maintenance mode {}",
+        if dto.enabled { "enabled" } else { "disabled" }
+    );
+
+    http_ok(MaintenanceDtoResponse {
+        enabled: mode.is_enabled(),
+    })
+}
+
+#[get("users/{id}/raw", wrap = "from_fn(timeout)")]
+async fn raw_user(
+    _admin: AdminAuth,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let Ok(object_id) = ObjectId::parse_str(&id) else {
+        return http_bad_request(format!("Invalid user id {}", id));
+    };
+
+    match users_service::find_raw_by_id(store.get_ref(), &config_scope(&config), object_id).await {
+        Ok(Some(document)) => http_ok(Bson::Document(document).into_relaxed_extjson()),
+        Ok(None) => http_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::UserNotFound,
+            format!("User not found for id {}", id),
+        ),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error(format!("Generic error finding id {}", id))
+        }
+    }
+}
+
+#[post("render/{template}", wrap = "from_fn(timeout)")]
+async fn render_template(
+    _admin: AdminAuth,
+    hb: Option<web::Data<Handlebars<'_>>>,
+    config: web::Data<Settings>,
+    template: web::Path<String>,
+    context: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    if !config.dev_mode {
+        return http_forbidden("Template rendering is only available in dev mode".into());
+    }
+    // templates are not loaded with ENABLE_WEB_UI=false
+    let Some(hb) = hb else {
+        return http_not_found("Web UI is disabled, no template is registered".into());
+    };
+
+    let template = template.into_inner();
+    if !hb.has_template(&template) {
+        return http_not_found(format!("Template {} is not registered", template));
+    }
+
+    // dev mode: render errors are returned in the response
+    http_render(&hb, &template, &context.into_inner(), true)
+}
+
+#[delete("users", wrap = "from_fn(timeout)")]
+async fn wipe_users(
+    _admin: AdminAuth,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    dto: web::Json<WipeUsersDtoRequest>,
+) -> HttpResponse {
+    if !config.destructive_ops {
+        return http_forbidden("Destructive operations are disabled".into());
+    }
+    if !config.is_delete_confirmed(dto.confirm.as_deref()) {
+        return http_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ConfirmationRequired,
+            "Missing or invalid confirmation token".into(),
+        );
+    }
+
+    match users_service::delete_all(store.get_ref(), &config_scope(&config)).await {
+        Ok(deleted) => http_ok(DeletedCountDtoResponse { deleted }),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Wipe failed".into())
+        }
+    }
+}
+
+/// Service configuration for admin routes.
+///
+/// Registers all admin endpoint handlers with the Actix-web application.
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(effective_config);
+    cfg.service(reindex);
+    cfg.service(set_maintenance);
+    cfg.service(raw_user);
+    cfg.service(render_template);
+    cfg.service(wipe_users);
+}
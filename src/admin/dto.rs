@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReindexDtoResponse {
+    pub collection: String,
+    /// Indexes created by this run.
+    pub created: Vec<String>,
+    /// Indexes that were already present.
+    pub existing: Vec<String>,
+}
+
+/// Body of `POST /admin/maintenance`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MaintenanceDtoRequest {
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MaintenanceDtoResponse {
+    pub enabled: bool,
+}
+
+/// Body of `DELETE /admin/users`, `confirm` must match the configured confirmation token.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WipeUsersDtoRequest {
+    pub confirm: Option<String>,
+}
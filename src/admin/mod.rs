@@ -0,0 +1,2 @@
+pub mod admin_controller;
+pub mod dto;
@@ -0,0 +1,36 @@
+use crate::{shared::middleware::timeout::timeout, users::users_controller::UsersApiDoc};
+use actix_web::{HttpResponse, get, http::header, middleware::from_fn, web};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+/// Path of the generated OpenAPI document.
+pub const OPENAPI_PATH: &str = "/api-docs/openapi.json";
+
+/// Entry page of Swagger UI, `/swagger-ui` redirects here.
+const SWAGGER_UI_INDEX: &str = "/swagger-ui/index.html";
+
+/// API documentation generated from the annotated handlers and DTOs with `utoipa`.
+///
+/// Registered at the root in main.rs when `ENABLE_API_DOCS` is on, before the web UI `/`
+/// scope. The routes are public, the documented endpoints keep their own authentication.
+///
+/// # Routes
+/// - `GET /api-docs/openapi.json` - OpenAPI 3.1 document of the users API
+/// - `GET /swagger-ui` - Swagger UI browsing that document
+
+// `NormalizePath` trims the trailing slash the Swagger UI service redirects the bare path
+// to, which would loop: the bare path is answered here, before that service is registered
+#[get("/swagger-ui", wrap = "from_fn(timeout)")]
+async fn swagger_ui_redirect() -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, SWAGGER_UI_INDEX))
+        .finish()
+}
+
+/// Service configuration for the API documentation routes.
+///
+/// Registers the OpenAPI document and the Swagger UI assets with the Actix-web application.
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(swagger_ui_redirect);
+    cfg.service(SwaggerUi::new("/swagger-ui/{_:.*}").url(OPENAPI_PATH, UsersApiDoc::openapi()));
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HealthDtoResponse {
+    /// `up` on `GET /health`, otherwise `ok`, `unavailable` when a dependency is down, or
+    /// `maintenance` when readiness is withdrawn by maintenance mode.
+    pub status: String,
+    /// Per-dependency status, only reported by `GET /health/ready`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<DependenciesDto>,
+}
+
+/// Status of each dependency, `up` or `down`, Redis is `disabled` when started without it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DependenciesDto {
+    pub mongodb: String,
+    pub redis: String,
+}
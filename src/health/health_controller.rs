@@ -0,0 +1,155 @@
+use crate::{
+    health::dto::{DependenciesDto, HealthDtoResponse},
+    shared::{
+        dto::response::http_ok,
+        middleware::{maintenance::MaintenanceMode, timeout::timeout},
+    },
+    users::users_store::UserStore,
+};
+use actix_web::{HttpResponse, get, middleware::from_fn, rt::time, web};
+use log::warn;
+use redis::aio::ConnectionManager;
+use std::time::Duration;
+
+/// Longest wait for a dependency ping, a silent one is reported `down`.
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// REST API controller for liveness and readiness probes.
+///
+/// All routes are prefixed with `/health` as specified in main.rs via `web::scope("/health")`
+/// and stay reachable in maintenance mode.
+///
+/// # Routes
+/// - `GET /health` - 200 `{"status": "up"}` while the process is running, touches no dependency
+/// - `GET /health/live` - 200 while the process is running
+/// - `GET /health/ready` - 200 when MongoDB and Redis answer a ping within `PING_TIMEOUT`,
+///   503 with the status of each dependency otherwise, or in maintenance mode. Redis is
+///   reported `disabled`, and not checked, when the application started without it
+///   (`REDIS_REQUIRED=false`)
+
+#[get("", wrap = "from_fn(timeout)")]
+async fn status() -> HttpResponse {
+    http_ok(HealthDtoResponse {
+        status: "up".into(),
+        dependencies: None,
+    })
+}
+
+#[get("live", wrap = "from_fn(timeout)")]
+async fn live() -> HttpResponse {
+    http_ok(HealthDtoResponse {
+        status: "ok".into(),
+        dependencies: None,
+    })
+}
+
+fn dependency_status(name: &str, res: Result<(), String>) -> String {
+    match res {
+        Ok(()) => "up".into(),
+        Err(err) => {
+            warn!("Readiness: {} is down: {}", name, err);
+            "down".into()
+        }
+    }
+}
+
+/// Result of `ping`, an error when it does not answer within `limit`.
+async fn ping_within(
+    limit: Duration,
+    ping: impl Future<Output = Result<(), String>>,
+) -> Result<(), String> {
+    time::timeout(limit, ping)
+        .await
+        .unwrap_or_else(|_| Err(format!("no answer within {}ms", limit.as_millis())))
+}
+
+#[get("ready", wrap = "from_fn(timeout)")]
+async fn ready(
+    mode: web::Data<MaintenanceMode>,
+    store: web::Data<dyn UserStore>,
+    redis: Option<web::Data<ConnectionManager>>,
+) -> HttpResponse {
+    if mode.is_enabled() {
+        return HttpResponse::ServiceUnavailable().json(HealthDtoResponse {
+            status: "maintenance".into(),
+            dependencies: None,
+        });
+    }
+
+    let (mongodb, redis) = tokio::join!(ping_within(PING_TIMEOUT, store.ping()), async {
+        let mut redis = redis?.get_ref().clone();
+        let ping = async move {
+            redis::cmd("PING")
+                .query_async::<String>(&mut redis)
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        };
+        Some(ping_within(PING_TIMEOUT, ping).await)
+    });
+    let up = mongodb.is_ok() && redis.as_ref().is_none_or(|redis| redis.is_ok());
+    let body = HealthDtoResponse {
+        status: if up { "ok" } else { "unavailable" }.into(),
+        dependencies: Some(DependenciesDto {
+            mongodb: dependency_status("mongodb", mongodb),
+            redis: match redis {
+                Some(redis) => dependency_status("redis", redis),
+                None => "disabled".into(),
+            },
+        }),
+    };
+
+    if up {
+        http_ok(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+/// Service configuration for health routes.
+///
+/// Registers all health endpoint handlers with the Actix-web application.
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(status);
+    cfg.service(live);
+    cfg.service(ready);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use actix_web::{App, http::StatusCode, test};
+
+    use crate::users::users_memory_store::MemoryUserStore;
+
+    #[actix_web::test]
+    async fn ready_without_redis_reports_it_disabled() {
+        let store: Arc<dyn UserStore> = Arc::new(MemoryUserStore::new(false));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(store))
+                .app_data(web::Data::new(MaintenanceMode::new(false, 30)))
+                .service(web::scope("/health").configure(config)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/health/ready").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: HealthDtoResponse = test::read_body_json(res).await;
+        let dependencies = body.dependencies.unwrap();
+        assert_eq!(dependencies.mongodb, "up");
+        assert_eq!(dependencies.redis, "disabled");
+    }
+
+    #[actix_web::test]
+    async fn silent_pings_are_reported_down() {
+        let limit = Duration::from_millis(10);
+        let res = ping_within(limit, std::future::pending()).await;
+        assert_eq!(res, Err("no answer within 10ms".into()));
+        assert_eq!(dependency_status("redis", res), "down");
+        assert_eq!(ping_within(limit, async { Ok(()) }).await, Ok(()));
+    }
+}
@@ -1,14 +1,85 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, format_description::well_known::Iso8601};
+
+/// Current layout version of [`FirstHitCache`].
+pub const FIRST_HIT_CACHE_VERSION: u8 = 1;
 
 /// Data transfer object for the home page.
 ///
-/// Contains the view model data that will be rendered
-/// in the `home.hbs` Handlebars template.
+/// The view model of the `home.hbs` Handlebars template, also answered as JSON by `GET /`.
+/// Every field is used by the template and the template uses no other field:
+/// `tests/home_view.rs` renders it in strict mode, so a drift fails instead of rendering
+/// blanks.
 #[derive(Serialize)]
 pub struct HomeData {
+    /// First hit of the home page, ISO 8601 formatted (see `FirstHitCache`).
+    pub first_hit: String,
+    /// Page title and heading.
+    pub title: String,
+    /// Home page visits of the current session, `None` without Redis (no sessions).
+    pub visits: Option<u64>,
+}
+
+/// First hit timestamp as cached in Redis (JSON, versioned).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FirstHitCache {
+    pub version: u8,
     /// ISO 8601 formatted
     pub first_hit: String,
+    /// ISO 8601 formatted, when the entry was written
+    pub stored_at: String,
+}
 
-    // Title
-    pub title: String,
+impl FirstHitCache {
+    pub fn new(first_hit: String, stored_at: String) -> Self {
+        FirstHitCache {
+            version: FIRST_HIT_CACHE_VERSION,
+            first_hit,
+            stored_at,
+        }
+    }
+
+    /// Current version with well formed timestamps, anything else must be recomputed.
+    pub fn is_valid(&self) -> bool {
+        self.version == FIRST_HIT_CACHE_VERSION
+            && OffsetDateTime::parse(&self.first_hit, &Iso8601::DEFAULT).is_ok()
+            && OffsetDateTime::parse(&self.stored_at, &Iso8601::DEFAULT).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::cache::json::{CacheError, decode};
+
+    const STAMP: &str = "2024-03-05T21:07:09.000000000Z";
+
+    #[test]
+    fn stored_entry_round_trips() {
+        let raw = serde_json::to_string(&FirstHitCache::new(STAMP.into(), STAMP.into())).unwrap();
+        let cached: FirstHitCache = decode(&raw).unwrap();
+        assert!(cached.is_valid());
+        assert_eq!(cached.first_hit, STAMP);
+    }
+
+    #[test]
+    fn corrupt_value_is_a_json_error() {
+        // e.g. the raw ISO string stored before the value was versioned JSON
+        for raw in [STAMP, "{\"first_hit\": ", "{\"version\": 1}"] {
+            assert!(matches!(
+                decode::<FirstHitCache>(raw),
+                Err(CacheError::Json(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn other_version_or_bad_timestamp_is_invalid() {
+        let mut cached = FirstHitCache::new(STAMP.into(), STAMP.into());
+        cached.version = FIRST_HIT_CACHE_VERSION + 1;
+        assert!(!cached.is_valid());
+
+        let cached = FirstHitCache::new("yesterday".into(), STAMP.into());
+        assert!(!cached.is_valid());
+    }
 }
@@ -1,61 +1,149 @@
-use actix_web::{HttpResponse, Result, get, web};
+use actix_session::Session;
+use actix_web::{
+    HttpRequest, HttpResponse, Result, get,
+    http::header::{self, HeaderValue},
+    middleware::from_fn,
+    web,
+};
 use handlebars::Handlebars;
-use redis::{AsyncCommands, RedisError, aio::ConnectionManager};
+use log::warn;
+use redis::aio::ConnectionManager;
 use time::{OffsetDateTime, format_description::well_known::Iso8601};
 
-use crate::{home::dto::HomeData, shared::config::settings::RedisKeys};
+use crate::{
+    home::dto::{FirstHitCache, HomeData},
+    shared::{
+        cache::json::{CacheError, apply_policy, get_json, set_json, set_json_ex},
+        config::settings::{RedisKeys, Settings},
+        dto::response::{http_ok, http_render, prefers_json},
+        middleware::timeout::timeout,
+    },
+};
 
-/// Serves the home page by rendering the Handlebars template.
+/// Session entry counting the home page visits.
+const VISITS_SESSION_KEY: &str = "visits";
+
+/// Serves the home page, as HTML or as JSON.
+///
+/// Renders the `home.hbs` template, unless `application/json` is the preferred media
+/// type of `Accept`: the same [`HomeData`] is then answered as JSON.
 ///
-/// This handler loads the `home.hbs` template
+/// With Redis, each visit increments a counter kept in the visitor session (see
+/// `build_session_middleware`).
 ///
 /// # Route
 /// `GET /` - Home page
 ///
 /// # Arguments
 /// * `hb` - Handlebars template engine instance
-/// * `redis` - Redis connection manager for caching
+/// * `redis` - Redis connection manager for caching, absent when Redis is not available
+/// * `session` - Visitor session, stored in Redis
+/// * `config` - Runtime configuration (dev mode exposes render errors)
 ///
 /// # Returns
-/// Rendered HTML page or error response
-#[get("")]
+/// Rendered HTML page, JSON data or error response, with `Vary: Accept`
+#[get("", wrap = "from_fn(timeout)")]
 async fn home(
+    req: HttpRequest,
     hb: web::Data<Handlebars<'_>>,
-    redis: web::Data<ConnectionManager>,
+    redis: Option<web::Data<ConnectionManager>>,
+    session: Session,
+    config: web::Data<Settings>,
 ) -> Result<HttpResponse> {
-    let now = OffsetDateTime::now_utc();
-    // find data in redis
-    let res: Result<String, RedisError> = redis
-        .get_ref()
-        .clone()
-        .get(RedisKeys::FirstHit.as_str())
-        .await;
+    let mut data = load_home_data(redis.as_ref().map(|redis| redis.get_ref()), &config).await?;
+    // without Redis the session middleware is not installed, nothing would be kept
+    if redis.is_some() {
+        data.visits = Some(count_visit(&session)?);
+    }
+
+    let mut response = if prefers_json(&req) {
+        http_ok(data)
+    } else {
+        http_render(&hb, "home", &data, config.dev_mode)
+    };
+    // caches must not serve one representation for the other
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+    Ok(response)
+}
 
-    let iso_date = match res {
-        Ok(res) => res, // if found
-        Err(_) => {
-            let iso_date = now
-                .format(&Iso8601::DEFAULT)
+/// Loads the home page data, the first hit timestamp being kept in Redis.
+///
+/// `CACHE_FAILURE_POLICY` decides whether a Redis failure is an error, `HOME_CACHE_TTL_SECS`
+/// sets the entry lifetime. The timestamp is regenerated once the entry expires. It is only
+/// written when the key is missing or unusable, never after a failed read: the stored value
+/// may still be valid. Without Redis the current time is shown on every request.
+async fn load_home_data(redis: Option<&ConnectionManager>, config: &Settings) -> Result<HomeData> {
+    let now = OffsetDateTime::now_utc();
+    let key = &RedisKeys::FirstHit.key(&config.redis_key_prefix);
+    let policy = config.cache_failure_policy;
+    // find data in redis, missing, malformed or outdated values are recomputed and stored
+    let Some(redis) = redis else {
+        return Ok(home_data(now_iso(now)?));
+    };
+    let (cached, store) = match get_json::<FirstHitCache>(redis, key).await {
+        Ok(Some(cached)) if cached.is_valid() => (Some(cached), false),
+        Ok(Some(cached)) => {
+            warn!("Discarding invalid first hit cache entry: {:?}", cached);
+            (None, true)
+        }
+        Ok(None) => (None, true),
+        Err(CacheError::Json(err)) => {
+            warn!("Discarding malformed first hit cache entry: {}", err);
+            (None, true)
+        }
+        // failing open, the page shows the current time and the entry is left untouched
+        Err(err) => {
+            apply_policy(Err(err), policy, None::<FirstHitCache>)
                 .map_err(actix_web::error::ErrorInternalServerError)?;
-            let res: Result<String, RedisError> = redis
-                .get_ref()
-                .clone()
-                .set(RedisKeys::FirstHit.as_str(), iso_date.as_str())
-                .await;
-            res.map_err(actix_web::error::ErrorInternalServerError)?;
+            (None, false)
+        }
+    };
+
+    let iso_date = match cached {
+        Some(cached) => cached.first_hit, // if found
+        None => {
+            let iso_date = now_iso(now)?;
+            if store {
+                let entry = FirstHitCache::new(iso_date.clone(), iso_date.clone());
+                let stored = match config.home_cache_ttl_secs {
+                    0 => set_json(redis, key, &entry).await,
+                    ttl => set_json_ex(redis, key, &entry, ttl).await,
+                };
+                apply_policy(stored, policy, ())
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
             iso_date
         }
     };
 
-    let data = HomeData {
-        first_hit: iso_date,
+    Ok(home_data(iso_date))
+}
+
+fn home_data(first_hit: String) -> HomeData {
+    HomeData {
+        first_hit,
         title: "Rust web starter".to_owned(),
-    };
-    let body = hb
-        .render("home", &data)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+        visits: None,
+    }
+}
+
+/// Increments the visit counter of `session`, a malformed counter starts over.
+fn count_visit(session: &Session) -> Result<u64> {
+    let visits = session
+        .get::<u64>(VISITS_SESSION_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+        + 1;
+    session.insert(VISITS_SESSION_KEY, visits)?;
+    Ok(visits)
+}
 
-    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+fn now_iso(now: OffsetDateTime) -> Result<String> {
+    now.format(&Iso8601::DEFAULT)
+        .map_err(actix_web::error::ErrorInternalServerError)
 }
 
 /// Service configuration for home page routes.
@@ -1,3 +1,8 @@
+pub mod admin;
+pub mod api_docs;
+pub mod health;
 pub mod home;
 pub mod shared;
 pub mod users;
+pub mod version;
+pub mod well_known;
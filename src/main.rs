@@ -14,12 +14,16 @@
 //! The application follows a modular architecture with:
 //! - Middleware stack: panic handling, path normalization, request logging
 //! - Dependency injection via Actix-web's `Data` extractor
+//! - Users storage behind the `UserStore` trait, MongoDB or in-memory (`STORAGE_BACKEND`)
 //! - Separate controller modules for API routes (e.g., users module)
+//! - Liveness/readiness probes on `/health`, maintenance mode toggled via `/admin`
 //!
 //! # Quick Start
 //!
 //! ```bash
-//! cargo run
+//! cargo run                    # same as `cargo run -- serve`
+//! cargo run -- seed --count 50 # creates the indexes and inserts demo users
+//! cargo run -- indexes         # creates the indexes and exits
 //! ```
 //!
 //! The server listens on the configured address (default: 0.0.0.0:3000).
@@ -32,57 +36,311 @@
 use actix_files::Files;
 use actix_web::{
     App, HttpServer,
-    middleware::{Logger, NormalizePath, TrailingSlash},
+    middleware::{Compress, Condition, Logger, NormalizePath, TrailingSlash, from_fn},
     web,
 };
 use actix_web_lab::middleware::CatchPanic;
-use log::debug;
+use clap::{Parser, Subcommand};
+use log::{error, info, warn};
 use rust_web_starter::{
-    home,
-    shared::config::settings::{
-        build_handlebars, build_server_bind, get_assets_dir, init_logger, init_mongodb, init_redis,
+    admin, api_docs, health, home,
+    shared::{
+        auth::jwt::jwt_auth,
+        concurrency::db_limiter::init_db_limiter,
+        config::settings::{
+            LogFormat, RateLimitBackend, Settings, StorageBackend, build_cors, build_handlebars,
+            build_json_config, build_session_key, build_session_middleware, build_tls_config,
+            get_assets_dir, init_logger, init_mongodb, init_redis, load_dotenv,
+            log_startup_summary,
+        },
+        dto::datetime::init_json_date_format,
+        middleware::{
+            access_log::json_access_log,
+            charset::utf8_charset,
+            maintenance::{MaintenanceMode, maintenance},
+            rate_limit::{LocalRateLimiter, rate_limit},
+            request_id::request_id,
+            security_headers::security_headers,
+            strict_json::strict_json,
+        },
+        tasks::{
+            redis_keepalive::redis_keepalive,
+            shutdown::{Shutdown, termination_signal},
+            supervisor::spawn_supervised,
+        },
     },
-    users,
+    users::{
+        self,
+        users_memory_store::MemoryUserStore,
+        users_store::{MongoUserStore, StoreScope, UserStore},
+    },
+    version, well_known,
 };
+use std::{sync::Arc, time::Duration};
+
+/// Demo users inserted by `seed` without `--count`.
+const DEFAULT_SEED_COUNT: usize = 20;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP server (default)
+    Serve,
+    /// Create the users indexes, insert demo users and exit
+    Seed {
+        /// Demo users to insert, `demoN@example.com` ones already stored are skipped
+        #[arg(short = 'n', long, default_value_t = DEFAULT_SEED_COUNT)]
+        count: usize,
+    },
+    /// Create the users indexes and exit
+    Indexes,
+}
 
 /// Application entry point.
 ///
-/// Initializes the Actix-web server, configures Handlebars templating,
-/// and starts listening for HTTP requests on 0.0.0.0:3000.
+/// Runs the command line subcommand, by default initializes the Actix-web server,
+/// configures Handlebars templating and starts listening for HTTP requests on 0.0.0.0:3000.
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let command = Cli::parse().command.unwrap_or(Command::Serve);
+    let dotenv = load_dotenv(); // before anything reads the environment
     init_logger();
-    let handlebars = build_handlebars();
-    let assets_dir = get_assets_dir();
-    let server_bind = build_server_bind();
-    let mongodb_client = init_mongodb().await;
-    let redis_manager = init_redis().await;
+    dotenv.log();
+    // every variable is validated here, all invalid ones are reported together
+    let settings = match Settings::from_env() {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let server_bind = settings.bind.clone();
+    let shutdown_timeout = Duration::from_secs(settings.shutdown_timeout_secs);
+    let http_workers = settings.http_workers;
+    let keep_alive = Duration::from_secs(settings.http_keep_alive_secs);
+    let json_log = settings.log_format == LogFormat::Json;
+    init_json_date_format(settings.json_date_format);
+    init_db_limiter(settings.mongodb_max_concurrent_ops);
+    let user_store: Arc<dyn UserStore> = match settings.storage_backend {
+        StorageBackend::Mongodb => Arc::new(MongoUserStore::new(
+            init_mongodb(&settings).await,
+            settings.users_collection.clone(),
+            settings.email_reuse_after_delete,
+            settings.mongodb_transactions,
+        )),
+        StorageBackend::Memory => {
+            warn!(
+                "STORAGE_BACKEND is memory: users are kept in process memory and lost on restart"
+            );
+            Arc::new(MemoryUserStore::new(settings.email_reuse_after_delete))
+        }
+    };
+    let startup_scope = StoreScope {
+        database: settings.database.clone(),
+        comment: None,
+    };
+    let indexes = users::users_service::ensure_indexes(user_store.as_ref(), &startup_scope).await;
+    match &indexes {
+        Ok(report) => info!(
+            "Users indexes ready (created: {:?}, existing: {:?})",
+            report.created, report.existing
+        ),
+        Err(err) => error!("{}", err),
+    }
+    match command {
+        Command::Serve => {}
+        Command::Indexes => std::process::exit(if indexes.is_ok() { 0 } else { 1 }),
+        Command::Seed { count } => {
+            if indexes.is_err() {
+                std::process::exit(1);
+            }
+            if settings.storage_backend == StorageBackend::Memory {
+                warn!("STORAGE_BACKEND is memory: seeded users are lost when the command exits");
+            }
+            match users::users_service::seed(user_store.as_ref(), &startup_scope, count).await {
+                Ok(report) => {
+                    info!(
+                        "Seeded {} demo users, {} skipped (email already taken)",
+                        report.inserted, report.skipped
+                    );
+                    return Ok(());
+                }
+                Err(err) => {
+                    error!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    let tls_config = build_tls_config(&settings);
+    // API-only deployments (ENABLE_WEB_UI=false) need neither templates nor assets
+    let web_ui = settings.enable_web_ui.then(|| {
+        (
+            web::Data::new(build_handlebars(&settings, |_| {})),
+            get_assets_dir(&settings),
+        )
+    });
+    // `None` when Redis is unreachable and REDIS_REQUIRED=false
+    let redis_manager = init_redis(&settings).await;
+    if redis_manager.is_none() && settings.rate_limit.backend == RateLimitBackend::Redis {
+        warn!("RATE_LIMIT_BACKEND is redis but Redis is unavailable: requests are not limited");
+    }
+    // background tasks, stopped and awaited once the server has shut down
+    let shutdown = Shutdown::new();
+    let shutdown_drain = Duration::from_secs(settings.shutdown_drain_secs);
+    if let Some(redis) = &redis_manager
+        && settings.redis_keepalive_secs > 0
+    {
+        let redis = redis.clone();
+        let period = Duration::from_secs(settings.redis_keepalive_secs);
+        spawn_supervised(
+            "redis-keepalive",
+            settings.task_restart.clone(),
+            &shutdown,
+            move |signal| redis_keepalive(redis.clone(), period, signal),
+        );
+    }
 
-    let handlebars_ref = web::Data::new(handlebars);
-    let mongodb_ref = web::Data::new(mongodb_client);
-    let redis_ref = web::Data::new(redis_manager);
+    // home page sessions need Redis, one key for every worker
+    let session_key =
+        (web_ui.is_some() && redis_manager.is_some()).then(|| build_session_key(&settings.session));
 
-    debug!(
-        "Server bind: address {} port {}",
-        server_bind.addr, server_bind.port
+    let user_store_ref: web::Data<dyn UserStore> = web::Data::from(user_store);
+    let redis_ref = redis_manager.map(web::Data::new);
+    let rate_limiter_ref = web::Data::new(LocalRateLimiter::new(
+        settings.rate_limit.burst,
+        settings.rate_limit.refill_per_sec,
+    ));
+    let maintenance_ref = web::Data::new(MaintenanceMode::new(
+        settings.maintenance_mode,
+        settings.maintenance_retry_after_secs,
+    ));
+    let user_reads_ref = web::Data::new(users::users_service::UserReads::new());
+    let rate_limit_enabled = settings.rate_limit.backend != RateLimitBackend::Disabled;
+    let cors = settings.cors.clone();
+    let max_json_bytes = settings.max_json_bytes;
+    let api_docs_enabled = settings.enable_api_docs;
+    // actix default format followed by the request id response header
+    let text_log_format = format!(
+        r#"%a "%r" %s %b "%{{Referer}}i" "%{{User-Agent}}i" %T %{{{}}}o"#,
+        settings.request_id_header
     );
+    // every init step is done, the effective configuration is logged in one block
+    log_startup_summary(&settings);
+    let config_ref = web::Data::new(settings);
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(mongodb_ref.clone())
-            .app_data(redis_ref.clone())
-            .app_data(handlebars_ref.clone())
+            .app_data(user_store_ref.clone())
+            .app_data(config_ref.clone())
+            .app_data(rate_limiter_ref.clone())
+            .app_data(maintenance_ref.clone())
+            .app_data(user_reads_ref.clone())
+            .app_data(build_json_config(max_json_bytes)) // MAX_JSON_BYTES, JSON error bodies
+            .app_data(web::PayloadConfig::new(max_json_bytes)) // raw bodies read by strict_json
+            // absent without Redis, handlers take `Option<web::Data<ConnectionManager>>`
+            .configure(|cfg| {
+                if let Some(redis) = &redis_ref {
+                    cfg.app_data(redis.clone());
+                }
+            })
             .wrap(NormalizePath::new(TrailingSlash::Trim)) // normalize path
+            .wrap(from_fn(strict_json)) // 400 on duplicate JSON keys when STRICT_JSON_KEYS
+            .wrap(from_fn(utf8_charset)) // charset=utf-8 on JSON and text responses
+            .wrap(Compress::default()) // brotli, gzip or zstd per Accept-Encoding
+            .wrap(from_fn(maintenance)) // 503 outside /health and /admin while in maintenance
+            .wrap(Condition::new(rate_limit_enabled, from_fn(rate_limit))) // per-IP 429
             .wrap(CatchPanic::default()) // CatchPanic must be before Logger
-            .wrap(Logger::default()) // last wrap
-            // render, response text/html on path /
-            .service(web::scope("/").configure(home::home_render::config))
-            // static assets, serve as is
-            .service(Files::new("/assets", assets_dir.clone()))
-            // rest controllers, response application/json on path /users
-            .service(web::scope("/users").configure(users::users_controller::config))
+            .wrap(from_fn(security_headers)) // nosniff, frame, referrer and CSP_HEADER policies
+            .wrap(Condition::new(cors.is_enabled(), build_cors(&cors))) // CORS_ALLOWED_ORIGINS, answers preflights
+            .wrap(Condition::new(json_log, from_fn(json_access_log))) // LOG_FORMAT=json
+            .wrap(from_fn(request_id)) // REQUEST_ID_HEADER, also on recovered panics
+            .wrap(Condition::new(
+                !json_log,
+                Logger::new(&text_log_format).exclude("/favicon.ico"),
+            )) // last wrap
+            // favicon, registered before the `/` scope
+            .configure(well_known::well_known_controller::favicon_config)
+            // OpenAPI document and Swagger UI, registered before the `/` scope
+            .configure(|cfg| {
+                if api_docs_enabled {
+                    api_docs::api_docs_controller::config(cfg);
+                }
+            })
+            // web UI: render, response text/html on path /, static assets served as is
+            .configure(|cfg| {
+                if let Some((handlebars, assets_dir)) = &web_ui {
+                    let home = web::scope("/").configure(home::home_render::config);
+                    cfg.app_data(handlebars.clone());
+                    // visitor sessions stored through the shared Redis connection, SESSION_*
+                    match (&redis_ref, &session_key) {
+                        (Some(redis), Some(key)) => {
+                            cfg.service(home.wrap(build_session_middleware(
+                                &config_ref,
+                                redis.get_ref().clone(),
+                                key.clone(),
+                            )))
+                        }
+                        _ => cfg.service(home),
+                    };
+                    cfg.service(Files::new("/assets", assets_dir));
+                }
+            })
+            // rest controllers, response application/json on path /users, JWT_SECRET bearer auth
+            .service(
+                web::scope("/users")
+                    .wrap(from_fn(jwt_auth))
+                    .configure(|cfg| {
+                        users::users_controller::config(cfg);
+                        if web_ui.is_some() {
+                            users::users_render::config(cfg);
+                        }
+                    }),
+            )
+            // liveness and readiness probes
+            .service(web::scope("/health").configure(health::health_controller::config))
+            // standard paths probed by tools (RFC 8615)
+            .service(
+                web::scope("/.well-known").configure(well_known::well_known_controller::config),
+            )
+            // build and runtime information
+            .service(web::scope("/version").configure(version::version_controller::config))
+            // admin controllers, protected by ADMIN_TOKEN
+            .service(web::scope("/admin").configure(admin::admin_controller::config))
     })
-    .bind((server_bind.addr, server_bind.port))?
-    .run()
-    .await
+    // signals are handled below, to log the shutdown
+    .disable_signals()
+    .workers(http_workers)
+    // a zero duration disables keep-alive
+    .keep_alive(keep_alive)
+    .shutdown_timeout(shutdown_timeout.as_secs());
+    let bind = (server_bind.addr, server_bind.port);
+    let server = match tls_config {
+        Some(tls) => server.bind_rustls_0_23(bind, tls)?,
+        None => server.bind(bind)?,
+    }
+    .run();
+
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        let signal = termination_signal().await;
+        info!(
+            "{} received, shutting down: draining in-flight requests for up to {:?}",
+            signal, shutdown_timeout
+        );
+        // graceful: stop accepting, wait for in-flight requests up to `shutdown_timeout`
+        handle.stop(true).await;
+    });
+
+    let served = server.await;
+    shutdown.drain(shutdown_drain).await;
+    info!("Shutdown complete");
+    served
 }
@@ -0,0 +1,73 @@
+//! Authentication for administrative endpoints.
+//!
+//! Admin routes declare an [`AdminAuth`] argument, the request is rejected
+//! before the handler runs unless it carries `Authorization: Bearer <ADMIN_TOKEN>`.
+use std::future::{Ready, ready};
+
+use actix_web::{
+    FromRequest, HttpRequest,
+    dev::Payload,
+    error::InternalError,
+    http::header::{self, HeaderMap},
+    web,
+};
+use log::warn;
+use subtle::ConstantTimeEq;
+
+use crate::shared::{
+    config::settings::Settings,
+    dto::response::{http_forbidden, http_unauthorized},
+};
+
+/// Extractor proving the caller presented a valid admin token.
+pub struct AdminAuth;
+
+impl FromRequest for AdminAuth {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(authorize(req))
+    }
+}
+
+/// Compares a presented token with `ADMIN_TOKEN` in constant time, so response timing
+/// does not reveal how many leading bytes matched.
+pub fn is_admin_token(token: &str, expected: &str) -> bool {
+    token.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+/// Token of an `Authorization: Bearer <token>` header, `None` for any other scheme.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+fn authorize(req: &HttpRequest) -> Result<AdminAuth, actix_web::Error> {
+    let expected = req
+        .app_data::<web::Data<Settings>>()
+        .and_then(|config| config.admin_token.clone());
+
+    let Some(expected) = expected else {
+        warn!(
+            "Admin endpoint {} called but ADMIN_TOKEN is not configured",
+            req.path()
+        );
+        return Err(InternalError::from_response(
+            "admin endpoints disabled",
+            http_forbidden("Admin endpoints are disabled".into()),
+        )
+        .into());
+    };
+
+    match bearer_token(req.headers()) {
+        Some(token) if is_admin_token(token, &expected) => Ok(AdminAuth),
+        _ => Err(InternalError::from_response(
+            "invalid admin token",
+            http_unauthorized("Invalid or missing admin token".into()),
+        )
+        .into()),
+    }
+}
@@ -0,0 +1,180 @@
+//! Bearer JWT authentication of the `/users` scope.
+//!
+//! When `JWT_SECRET` is set, requests must carry `Authorization: Bearer <token>` with an
+//! HS256 token signed with it and not expired, otherwise they are answered 401. The
+//! token [`Claims`] are stored in the request extensions, handlers read the caller with
+//! a `web::ReqData<Claims>` argument. The admin token is accepted too, without claims, so
+//! admin-only routes below `/users` (e.g. `GET /users/export.zip`) keep working.
+//!
+//! Without `JWT_SECRET` requests pass through unauthenticated.
+use actix_web::{
+    Error, HttpMessage, HttpResponse,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderValue},
+    middleware::Next,
+    web,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, errors::ErrorKind};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::shared::{
+    auth::admin::{bearer_token, is_admin_token},
+    config::settings::Settings,
+    dto::response::http_unauthorized,
+};
+
+/// Claims of a valid token, other claims of the token are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Claims {
+    /// Caller identity.
+    pub sub: String,
+    /// Expiration, seconds since the Unix epoch.
+    pub exp: u64,
+}
+
+/// 401 with the `WWW-Authenticate` challenge of RFC 6750.
+fn unauthorized(message: &str, challenge: &'static str) -> HttpResponse {
+    let mut res = http_unauthorized(message.into());
+    res.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static(challenge),
+    );
+    res
+}
+
+/// JWT middleware, wrap the protected scope with `middleware::from_fn(jwt_auth)`.
+///
+/// Requires `web::Data<Settings>`, requests pass through when it is missing or when
+/// `JWT_SECRET` is not set.
+pub async fn jwt_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(config) = req.app_data::<web::Data<Settings>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let Some(secret) = config.jwt_secret.as_deref() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    let Some(token) = bearer_token(req.headers()) else {
+        return Ok(req.into_response(unauthorized("Missing bearer token", "Bearer")));
+    };
+    if config
+        .admin_token
+        .as_deref()
+        .is_some_and(|expected| is_admin_token(token, expected))
+    {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let key = DecodingKey::from_secret(secret.as_bytes());
+    match decode::<Claims>(token, &key, &Validation::new(Algorithm::HS256)) {
+        Ok(data) => {
+            req.extensions_mut().insert(data.claims);
+            Ok(next.call(req).await?.map_into_boxed_body())
+        }
+        Err(err) => {
+            let message = match err.kind() {
+                ErrorKind::ExpiredSignature => "Token expired",
+                _ => "Invalid token",
+            };
+            debug!("JWT rejected on {}: {}", req.path(), err);
+            Ok(req.into_response(unauthorized(message, r#"Bearer error="invalid_token""#)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use actix_web::{
+        App, HttpResponse,
+        http::StatusCode,
+        middleware::from_fn,
+        test::{self, TestRequest},
+    };
+    use jsonwebtoken::{EncodingKey, Header, encode};
+
+    use crate::shared::{config::settings::Settings, dto::response::ErrorResponse};
+
+    const SECRET: &str = "test-secret";
+
+    fn token(secret: &str, exp_offset_secs: i64) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let claims = Claims {
+            sub: "user-42".into(),
+            exp: (now + exp_offset_secs) as u64,
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    async fn whoami(claims: Option<web::ReqData<Claims>>) -> HttpResponse {
+        HttpResponse::Ok().body(claims.map(|c| c.sub.clone()).unwrap_or_default())
+    }
+
+    #[actix_web::test]
+    async fn only_valid_tokens_reach_the_handler() {
+        let mut config = Settings::defaults();
+        config.jwt_secret = Some(SECRET.into());
+        config.admin_token = Some("admin-token".into());
+        let app = test::init_service(
+            App::new().app_data(web::Data::new(config)).service(
+                web::scope("/users")
+                    .wrap(from_fn(jwt_auth))
+                    .route("", web::get().to(whoami)),
+            ),
+        )
+        .await;
+
+        let call = |authorization: Option<String>| {
+            let mut req = TestRequest::get().uri("/users");
+            if let Some(value) = authorization {
+                req = req.insert_header((header::AUTHORIZATION, value));
+            }
+            test::call_service(&app, req.to_request())
+        };
+
+        let res = call(Some(format!("Bearer {}", token(SECRET, 3600)))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "user-42");
+
+        let res = call(Some("Bearer admin-token".into())).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        for (authorization, message) in [
+            (None, "Missing bearer token"),
+            (
+                Some(format!("Basic {}", token(SECRET, 3600))),
+                "Missing bearer token",
+            ),
+            (
+                Some(format!("Bearer {}", token(SECRET, -3600))),
+                "Token expired",
+            ),
+            (
+                Some(format!("Bearer {}", token("other", 3600))),
+                "Invalid token",
+            ),
+            (Some("Bearer garbage".into()), "Invalid token"),
+        ] {
+            let res = call(authorization).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            assert!(res.headers().contains_key(header::WWW_AUTHENTICATE));
+            let body: ErrorResponse = test::read_body_json(res).await;
+            assert_eq!(body.message, message);
+        }
+    }
+}
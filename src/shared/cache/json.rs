@@ -0,0 +1,138 @@
+//! Structured JSON values in Redis.
+//!
+//! Values are stored as JSON documents and decoded into typed structs on read, so a
+//! value in an unexpected shape (older format, manual edit, truncation) is reported as
+//! [`CacheError::Json`] instead of being used verbatim. Callers usually treat it as a
+//! miss and overwrite the entry.
+//!
+//! Redis failures are left to the caller, which passes the result through
+//! [`apply_policy`] to honor `CACHE_FAILURE_POLICY`.
+use std::fmt;
+
+use log::warn;
+use redis::{AsyncCommands, RedisError, aio::ConnectionManager};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::shared::config::settings::CacheFailurePolicy;
+
+#[derive(Debug)]
+pub enum CacheError {
+    /// Redis command failed.
+    Redis(RedisError),
+    /// Cached value is not valid JSON for the requested type (or could not be encoded).
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Redis(err) => write!(f, "cache redis error: {}", err),
+            CacheError::Json(err) => write!(f, "cache value malformed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+/// Applies the failure policy to the result of a cache command.
+///
+/// Failing open, a Redis error is logged and replaced by `fallback` (`None` for a read,
+/// `()` for a write). Failing closed, it is returned. JSON errors are always returned,
+/// they are not a Redis outage and callers decide how to handle them.
+pub fn apply_policy<T>(
+    result: Result<T, CacheError>,
+    policy: CacheFailurePolicy,
+    fallback: T,
+) -> Result<T, CacheError> {
+    match result {
+        Err(CacheError::Redis(err)) if policy == CacheFailurePolicy::FailOpen => {
+            warn!("Cache unavailable, continuing without it: {}", err);
+            Ok(fallback)
+        }
+        other => other,
+    }
+}
+
+/// Decodes a raw cached value.
+pub fn decode<T: DeserializeOwned>(raw: &str) -> Result<T, CacheError> {
+    serde_json::from_str(raw).map_err(CacheError::Json)
+}
+
+/// Reads `key` and decodes it, `Ok(None)` when the key does not exist.
+pub async fn get_json<T: DeserializeOwned>(
+    redis: &ConnectionManager,
+    key: &str,
+) -> Result<Option<T>, CacheError> {
+    let raw: Option<String> = redis.clone().get(key).await.map_err(CacheError::Redis)?;
+
+    raw.as_deref().map(decode).transpose()
+}
+
+/// Encodes `value` as JSON and stores it under `key`.
+pub async fn set_json<T: Serialize>(
+    redis: &ConnectionManager,
+    key: &str,
+    value: &T,
+) -> Result<(), CacheError> {
+    let raw = serde_json::to_string(value).map_err(CacheError::Json)?;
+    redis
+        .clone()
+        .set::<_, _, ()>(key, raw)
+        .await
+        .map_err(CacheError::Redis)
+}
+
+/// Encodes `value` as JSON and stores it under `key`, expiring after `ttl_secs`.
+pub async fn set_json_ex<T: Serialize>(
+    redis: &ConnectionManager,
+    key: &str,
+    value: &T,
+    ttl_secs: u64,
+) -> Result<(), CacheError> {
+    let raw = serde_json::to_string(value).map_err(CacheError::Json)?;
+    redis
+        .clone()
+        .set_ex::<_, _, ()>(key, raw, ttl_secs)
+        .await
+        .map_err(CacheError::Redis)
+}
+
+/// Removes `key`, a missing key is not an error.
+pub async fn delete(redis: &ConnectionManager, key: &str) -> Result<(), CacheError> {
+    redis
+        .clone()
+        .del::<_, ()>(key)
+        .await
+        .map_err(CacheError::Redis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::ErrorKind;
+
+    fn redis_down() -> Result<Option<u8>, CacheError> {
+        Err(CacheError::Redis(RedisError::from((
+            ErrorKind::IoError,
+            "connection reset",
+        ))))
+    }
+
+    #[test]
+    fn fail_open_turns_redis_errors_into_the_fallback() {
+        let res = apply_policy(redis_down(), CacheFailurePolicy::FailOpen, None);
+        assert!(matches!(res, Ok(None)));
+    }
+
+    #[test]
+    fn fail_closed_keeps_redis_errors() {
+        let res = apply_policy(redis_down(), CacheFailurePolicy::FailClosed, None);
+        assert!(matches!(res, Err(CacheError::Redis(_))));
+    }
+
+    #[test]
+    fn json_errors_are_never_swallowed() {
+        let res = apply_policy(decode::<u8>("{"), CacheFailurePolicy::FailOpen, 0);
+        assert!(matches!(res, Err(CacheError::Json(_))));
+    }
+}
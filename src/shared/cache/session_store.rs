@@ -0,0 +1,141 @@
+//! Session state in Redis, for the session middleware of `build_session_middleware`.
+//!
+//! The store shares the application `ConnectionManager` (the `REDIS_URI` connection of
+//! `init_redis`) instead of opening its own. A session is one JSON object of string
+//! values under `RedisKeys::Session`, expiring with the session.
+//!
+//! Redis failures honor `CACHE_FAILURE_POLICY`: failing open, an unreadable session is
+//! a new empty one and a failed write is dropped, the page is still served.
+use std::collections::HashMap;
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use redis::{AsyncCommands, aio::ConnectionManager};
+use uuid::Uuid;
+
+use crate::shared::{
+    cache::json::{CacheError, apply_policy, delete, get_json, set_json_ex},
+    config::settings::{CacheFailurePolicy, RedisKeys},
+};
+
+type SessionState = HashMap<String, String>;
+
+/// Redis session store over the shared connection.
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    redis: ConnectionManager,
+    key_prefix: String,
+    policy: CacheFailurePolicy,
+}
+
+impl RedisSessionStore {
+    pub fn new(redis: ConnectionManager, key_prefix: &str, policy: CacheFailurePolicy) -> Self {
+        RedisSessionStore {
+            redis,
+            key_prefix: key_prefix.to_string(),
+            policy,
+        }
+    }
+
+    fn key(&self, session_key: &SessionKey) -> String {
+        RedisKeys::Session {
+            key: session_key.as_ref(),
+        }
+        .key(&self.key_prefix)
+    }
+
+    async fn store(
+        &self,
+        session_key: &SessionKey,
+        state: &SessionState,
+        ttl: &Duration,
+    ) -> Result<(), CacheError> {
+        let stored = set_json_ex(&self.redis, &self.key(session_key), state, ttl_secs(ttl)).await;
+        apply_policy(stored, self.policy, ())
+    }
+}
+
+impl SessionStore for RedisSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let loaded = get_json::<SessionState>(&self.redis, &self.key(session_key)).await;
+        match apply_policy(loaded, self.policy, None) {
+            Ok(state) => Ok(state),
+            Err(CacheError::Json(err)) => Err(LoadError::Deserialization(err.into())),
+            Err(err) => Err(LoadError::Other(err.into())),
+        }
+    }
+
+    async fn save(&self, state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        let session_key = new_session_key();
+        match self.store(&session_key, &state, ttl).await {
+            Ok(()) => Ok(session_key),
+            Err(CacheError::Json(err)) => Err(SaveError::Serialization(err.into())),
+            Err(err) => Err(SaveError::Other(err.into())),
+        }
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        match self.store(&session_key, &state, ttl).await {
+            Ok(()) => Ok(session_key),
+            Err(CacheError::Json(err)) => Err(UpdateError::Serialization(err.into())),
+            Err(err) => Err(UpdateError::Other(err.into())),
+        }
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        let ttl = i64::try_from(ttl_secs(ttl)).unwrap_or(i64::MAX);
+        let expired = self
+            .redis
+            .clone()
+            .expire::<_, ()>(self.key(session_key), ttl)
+            .await
+            .map_err(CacheError::Redis);
+        Ok(apply_policy(expired, self.policy, ())?)
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        let deleted = delete(&self.redis, &self.key(session_key)).await;
+        Ok(apply_policy(deleted, self.policy, ())?)
+    }
+}
+
+/// Random session key, 64 hex characters (two v4 UUIDs, 244 random bits).
+fn new_session_key() -> SessionKey {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+        .try_into()
+        .expect("64 characters is a valid session key length")
+}
+
+/// Redis expiry of a session, at least one second.
+fn ttl_secs(ttl: &Duration) -> u64 {
+    u64::try_from(ttl.whole_seconds()).unwrap_or(0).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_keys_are_unique_and_namespaced() {
+        let first = new_session_key();
+        let second = new_session_key();
+        assert_ne!(first.as_ref(), second.as_ref());
+        assert_eq!(first.as_ref().len(), 64);
+
+        let key = RedisKeys::Session {
+            key: first.as_ref(),
+        }
+        .key("prod");
+        assert_eq!(key, format!("prod:session:{}", first.as_ref()));
+        assert_eq!(ttl_secs(&Duration::ZERO), 1);
+    }
+}
@@ -0,0 +1,43 @@
+//! Global limit of concurrent MongoDB operations.
+//!
+//! HTTP rate limiting bounds requests, not the database work they trigger: one expensive
+//! request fanning out many queries can still exhaust the connection pool. Every data
+//! access takes a permit with [`db_permit`] and holds it for the whole operation (cursor
+//! iteration included), operations above the limit queue until a permit is released.
+//! `users_repository::stream_all` takes one per cursor batch instead: an export lasts as
+//! long as its download.
+use std::sync::OnceLock;
+
+use actix_web::rt::time::Instant;
+use log::debug;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+static SEMAPHORE: OnceLock<Semaphore> = OnceLock::new();
+
+/// Waits longer than this are logged.
+const SLOW_ACQUIRE_MS: u128 = 100;
+
+/// Sets the maximum number of concurrent MongoDB operations, 0 means unlimited.
+///
+/// Call once at startup, later calls are ignored.
+pub fn init_db_limiter(max_concurrent_ops: usize) {
+    if max_concurrent_ops > 0 {
+        let _ = SEMAPHORE.set(Semaphore::new(max_concurrent_ops));
+    }
+}
+
+/// Takes a permit for one MongoDB operation, `None` when unlimited.
+///
+/// Keep the returned permit alive (`let _permit = db_permit().await;`) until the
+/// operation completes.
+pub async fn db_permit() -> Option<SemaphorePermit<'static>> {
+    let semaphore = SEMAPHORE.get()?;
+    let started = Instant::now();
+    // The semaphore is never closed, acquire can't fail
+    let permit = semaphore.acquire().await.ok()?;
+    let waited = started.elapsed().as_millis();
+    if waited > SLOW_ACQUIRE_MS {
+        debug!("Waited {}ms for a MongoDB operation permit", waited);
+    }
+    Some(permit)
+}
@@ -0,0 +1,2 @@
+pub mod db_limiter;
+pub mod single_flight;
@@ -0,0 +1,73 @@
+//! Request coalescing (single-flight).
+//!
+//! Concurrent calls for the same key share one in-flight future: the first caller starts
+//! it, later callers await the same result until it completes. Results, errors included,
+//! are cloned to every waiter. Nothing is cached, the next call after completion starts
+//! a fresh future.
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+};
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+
+/// Map of in-flight futures keyed by `K`, shared via `web::Data`.
+pub struct SingleFlight<K, V> {
+    in_flight: Mutex<HashMap<K, Shared<BoxFuture<'static, V>>>>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        SingleFlight {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Awaits the in-flight future for `key`, or starts `fut` when there is none.
+    ///
+    /// `fut` is dropped without being polled when another caller already leads `key`.
+    pub async fn run<F>(&self, key: K, fut: F) -> V
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let shared = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.clone())
+            .or_insert_with(|| fut.boxed().shared())
+            .clone();
+
+        let result = shared.clone().await;
+
+        // First waiter to finish clears the entry, unless a newer flight replaced it
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if in_flight
+            .get(&key)
+            .is_some_and(|current| Shared::ptr_eq(current, &shared))
+        {
+            in_flight.remove(&key);
+        }
+
+        result
+    }
+}
+
+impl<K, V> Default for SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,120 @@
+//! Serde helpers for timestamps in DTOs.
+//!
+//! Every datetime field exposed in JSON goes through this module, so clients see one
+//! format everywhere, selected once at startup by `JSON_DATE_FORMAT`:
+//!
+//! ```ignore
+//! #[serde(with = "crate::shared::dto::datetime")]
+//! pub created_at: DateTime,
+//! #[serde(with = "crate::shared::dto::datetime::option", default)]
+//! pub deleted_at: Option<DateTime>,
+//! ```
+//!
+//! Deserialization accepts both an ISO-8601 string and epoch milliseconds, whatever the
+//! configured output format.
+use std::sync::OnceLock;
+
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Deserializer, Serializer, de::Error as _, ser::Error as _};
+use time::{
+    OffsetDateTime, UtcOffset,
+    format_description::well_known::{
+        Iso8601,
+        iso8601::{Config, TimePrecision},
+    },
+};
+
+use crate::shared::config::settings::JsonDateFormat;
+
+const ISO8601_MILLIS: u128 = Config::DEFAULT
+    .set_time_precision(TimePrecision::Second {
+        decimal_digits: std::num::NonZeroU8::new(3),
+    })
+    .encode();
+const ISO8601_SECONDS: u128 = Config::DEFAULT
+    .set_time_precision(TimePrecision::Second {
+        decimal_digits: None,
+    })
+    .encode();
+
+static FORMAT: OnceLock<JsonDateFormat> = OnceLock::new();
+
+/// Sets the JSON date format, call once at startup before serving requests.
+///
+/// Later calls are ignored, the format never changes while the server runs.
+pub fn init_json_date_format(format: JsonDateFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn json_date_format() -> JsonDateFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Formats `dt` as ISO-8601 UTC, with millisecond precision when `millis` is set.
+fn to_iso8601(dt: DateTime, millis: bool) -> Result<String, time::Error> {
+    let nanos = i128::from(dt.timestamp_millis()) * 1_000_000;
+    let dt = OffsetDateTime::from_unix_timestamp_nanos(nanos)?.to_offset(UtcOffset::UTC);
+    let formatted = if millis {
+        dt.format(&Iso8601::<ISO8601_MILLIS>)?
+    } else {
+        dt.format(&Iso8601::<ISO8601_SECONDS>)?
+    };
+    Ok(formatted)
+}
+
+pub fn serialize<S: Serializer>(dt: &DateTime, serializer: S) -> Result<S::Ok, S::Error> {
+    match json_date_format() {
+        JsonDateFormat::EpochMillis => serializer.serialize_i64(dt.timestamp_millis()),
+        JsonDateFormat::Iso8601Millis => {
+            serializer.serialize_str(&to_iso8601(*dt, true).map_err(S::Error::custom)?)
+        }
+        JsonDateFormat::Iso8601 => {
+            serializer.serialize_str(&to_iso8601(*dt, false).map_err(S::Error::custom)?)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDateTime {
+    EpochMillis(i64),
+    Iso8601(String),
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime, D::Error> {
+    match RawDateTime::deserialize(deserializer)? {
+        RawDateTime::EpochMillis(millis) => Ok(DateTime::from_millis(millis)),
+        RawDateTime::Iso8601(text) => {
+            let dt = OffsetDateTime::parse(&text, &Iso8601::DEFAULT).map_err(D::Error::custom)?;
+            let millis = dt.unix_timestamp_nanos() / 1_000_000;
+            i64::try_from(millis)
+                .map(DateTime::from_millis)
+                .map_err(D::Error::custom)
+        }
+    }
+}
+
+/// Same as the parent module, for `Option<DateTime>` fields (`null` when `None`).
+pub mod option {
+    use mongodb::bson::DateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        dt: &Option<DateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match dt {
+            Some(dt) => super::serialize(dt, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(deserialize_with = "super::deserialize")] DateTime);
+
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(dt)| dt))
+    }
+}
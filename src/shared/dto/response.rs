@@ -1,9 +1,104 @@
-use actix_web::HttpResponse;
+use actix_web::{
+    HttpRequest, HttpResponse,
+    http::{
+        StatusCode,
+        header::{self, Header},
+    },
+};
+use handlebars::Handlebars;
+use log::error;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Stable machine-readable error codes, clients branch on these instead of `message`.
+///
+/// Serialized as `SCREAMING_SNAKE_CASE` (e.g. `USER_NOT_FOUND`). Codes are part of the
+/// API: add new ones freely, never rename or repurpose existing ones.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// Invalid request parameters or body.
+    ValidationFailed,
+    /// A destructive request is missing the confirmation token.
+    ConfirmationRequired,
+    /// The requested user does not exist.
+    UserNotFound,
+    /// A user with the same email already exists.
+    EmailExists,
+    /// Text search requested but the users collection has no text index.
+    TextIndexMissing,
+    /// Any other missing resource.
+    NotFound,
+    Unauthorized,
+    Forbidden,
+    /// No acceptable representation (e.g. `Accept-Charset` without utf-8).
+    NotAcceptable,
+    /// The path exists but not for this method, see the `Allow` header.
+    MethodNotAllowed,
+    /// The request body exceeds the configured limit.
+    PayloadTooLarge,
+    /// The request body is not in a supported format (e.g. JSON without `application/json`).
+    UnsupportedMediaType,
+    RateLimited,
+    ServiceUnavailable,
+    /// The request did not complete within its deadline.
+    Timeout,
+    InternalError,
+}
+
+/// Error envelope of every JSON error response.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct ErrorResponse {
+    /// Human readable description, may change at any time.
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    /// Correlation id of the request, set on `AppError` responses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: String) -> Self {
+        ErrorResponse {
+            message,
+            code: Some(code),
+            request_id: None,
+        }
+    }
+}
+
+/// Outcome of a single sub-operation of a batch request.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct MultiStatusItem {
+    /// Identifier of the item the sub-operation targeted (e.g. a user id).
+    pub id: String,
+    /// HTTP status code of the sub-operation (e.g. 204, 400, 404).
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Body of a 207 Multi-Status response, one entry per sub-operation in request order.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct MultiStatusResponse {
+    pub results: Vec<MultiStatusItem>,
+}
+
+/// Returns true when the client sent `Prefer: return=minimal` (RFC 7240).
+pub fn prefers_minimal(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all("prefer")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|pref| pref.trim().eq_ignore_ascii_case("return=minimal"))
+}
+
+/// Returns true when `application/json` is the media type the client prefers most in
+/// `Accept` (highest quality, first on ties). Browsers list `text/html` first.
+pub fn prefers_json(req: &HttpRequest) -> bool {
+    header::Accept::parse(req)
+        .is_ok_and(|accept| accept.preference().essence_str() == "application/json")
 }
 
 /// Helper function for HTTP 200 OK JSON response.
@@ -11,17 +106,154 @@ pub fn http_ok(payload: impl Serialize) -> HttpResponse {
     HttpResponse::Ok().json(payload)
 }
 
+/// Helper function for HTTP 201 Created JSON response with the `Location` of the new resource.
+pub fn http_created(location: &str, payload: impl Serialize) -> HttpResponse {
+    HttpResponse::Created()
+        .insert_header((header::LOCATION, location))
+        .json(payload)
+}
+
 /// Helper function for HTTP 204 No Content response.
 pub fn http_no_content() -> HttpResponse {
     HttpResponse::NoContent().finish()
 }
 
+/// Helper function for HTTP 207 Multi-Status JSON response.
+///
+/// Used by batch endpoints whose sub-operations can have mixed outcomes, body shape:
+/// `{ "results": [ { "id": "...", "status": 204 }, { "id": "...", "status": 404, "message": "..." } ] }`
+pub fn http_multi_status(results: Vec<MultiStatusItem>) -> HttpResponse {
+    HttpResponse::MultiStatus().json(MultiStatusResponse { results })
+}
+
+/// Helper function for an error JSON response with an explicit status and code.
+///
+/// The `http_*` error helpers below use the generic code of their status, use this one
+/// when a more specific code applies (e.g. `USER_NOT_FOUND`, `EMAIL_EXISTS`).
+pub fn http_error(status: StatusCode, code: ErrorCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(ErrorResponse::new(code, message))
+}
+
 /// Helper function for HTTP 400 Bad Request JSON response.
 pub fn http_bad_request(message: String) -> HttpResponse {
-    HttpResponse::BadRequest().json(ErrorResponse { message })
+    HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::ValidationFailed, message))
+}
+
+/// Helper function for HTTP 401 Unauthorized JSON response.
+pub fn http_unauthorized(message: String) -> HttpResponse {
+    HttpResponse::Unauthorized().json(ErrorResponse::new(ErrorCode::Unauthorized, message))
+}
+
+/// Helper function for HTTP 403 Forbidden JSON response.
+pub fn http_forbidden(message: String) -> HttpResponse {
+    HttpResponse::Forbidden().json(ErrorResponse::new(ErrorCode::Forbidden, message))
+}
+
+/// Helper function for HTTP 404 Not Found JSON response.
+pub fn http_not_found(message: String) -> HttpResponse {
+    HttpResponse::NotFound().json(ErrorResponse::new(ErrorCode::NotFound, message))
+}
+
+/// Helper function for HTTP 405 Method Not Allowed JSON response.
+///
+/// Sets the `Allow` header to the methods supported by the path (e.g. `GET, POST`).
+pub fn http_method_not_allowed(allow: &[&str]) -> HttpResponse {
+    HttpResponse::MethodNotAllowed()
+        .insert_header((header::ALLOW, allow.join(", ")))
+        .json(ErrorResponse::new(
+            ErrorCode::MethodNotAllowed,
+            format!("Method not allowed, use one of: {}", allow.join(", ")),
+        ))
+}
+
+/// Helper function for HTTP 429 Too Many Requests JSON response with a `Retry-After` header.
+pub fn http_too_many_requests(retry_after_secs: u64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+        .json(ErrorResponse::new(
+            ErrorCode::RateLimited,
+            "Too many requests".into(),
+        ))
+}
+
+/// Helper function for HTTP 503 Service Unavailable JSON response.
+///
+/// Sets the `Retry-After` header (seconds).
+pub fn http_service_unavailable(message: String, retry_after_secs: u64) -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+        .json(ErrorResponse::new(ErrorCode::ServiceUnavailable, message))
+}
+
+/// Helper function for HTTP 504 Gateway Timeout JSON response.
+///
+/// Takes the request id: answered past the deadline, the response bypasses the
+/// `request_id` middleware that fills it in for `AppError` responses.
+pub fn http_gateway_timeout(message: String, request_id: Option<String>) -> HttpResponse {
+    let mut body = ErrorResponse::new(ErrorCode::Timeout, message);
+    body.request_id = request_id;
+    HttpResponse::GatewayTimeout().json(body)
 }
 
 /// Helper function for HTTP 500 Internal Server Error JSON response.
 pub fn http_internal_server_error(message: String) -> HttpResponse {
-    HttpResponse::InternalServerError().json(ErrorResponse { message })
+    HttpResponse::InternalServerError().json(ErrorResponse::new(ErrorCode::InternalError, message))
+}
+
+/// Helper function for HTTP 200 OK HTML response rendered from a Handlebars template.
+///
+/// Render failures (e.g. a template removed from disk at runtime) are logged with
+/// the template name and answered with a generic 500 page. The Handlebars error
+/// is only included in the body when `dev_mode` is enabled.
+pub fn http_render(
+    hb: &Handlebars<'_>,
+    template: &str,
+    data: &impl Serialize,
+    dev_mode: bool,
+) -> HttpResponse {
+    match hb.render(template, data) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body),
+        Err(e) => {
+            error!("Failed to render template {}: {}", template, e);
+            let detail = if dev_mode {
+                format!(
+                    "<pre>Template {}: {}</pre>",
+                    handlebars::html_escape(template),
+                    handlebars::html_escape(&e.to_string())
+                )
+            } else {
+                String::new()
+            };
+            HttpResponse::InternalServerError()
+                .content_type("text/html; charset=utf-8")
+                .body(format!(
+                    "<h1>Something went wrong</h1><p>The page could not be rendered.</p>{}",
+                    detail
+                ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn json_is_served_only_when_preferred() {
+        let accept = |value: &str| {
+            prefers_json(
+                &TestRequest::default()
+                    .insert_header((header::ACCEPT, value))
+                    .to_http_request(),
+            )
+        };
+        assert!(accept("application/json"));
+        assert!(accept("text/html;q=0.5, application/json"));
+        assert!(!accept("text/html,application/xhtml+xml,*/*;q=0.8"));
+        assert!(!accept("*/*"));
+        assert!(!prefers_json(&TestRequest::default().to_http_request()));
+    }
 }
@@ -0,0 +1,151 @@
+//! Typed application errors.
+//!
+//! Layers return [`AppError`] instead of a message string, so the cause travels up to the
+//! handler untouched. `AppError` implements `ResponseError`: handlers returning
+//! `Result<HttpResponse, AppError>` use `?` and every variant is answered with its status
+//! and an `ErrorResponse` body. Database and cache errors are logged with their details and
+//! answered with a generic message, they may expose internals.
+//!
+//! The `request_id` middleware adds the correlation id to the body of `AppError` responses,
+//! so a client reporting an error can be matched with the server logs.
+//!
+//! Layers not migrated yet still use `Result<_, String>` with `http_*` responses.
+use std::fmt;
+
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use log::error;
+
+use crate::shared::dto::response::{ErrorCode, ErrorResponse};
+
+#[derive(Debug)]
+pub enum AppError {
+    /// The requested user does not exist, the message names it. Answered like the handlers
+    /// answer a missing user: `404` with `USER_NOT_FOUND`.
+    NotFound(String),
+    /// Invalid request parameters or body.
+    Validation(String),
+    /// The email is already taken. Answered like the handlers answer a taken email: `400`
+    /// with `EMAIL_EXISTS`.
+    Duplicate(String),
+    Database(mongodb::error::Error),
+    Cache(redis::RedisError),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound(message) => write!(f, "not found: {}", message),
+            AppError::Validation(message) => write!(f, "validation failed: {}", message),
+            AppError::Duplicate(message) => write!(f, "duplicate: {}", message),
+            AppError::Database(err) => write!(f, "database error: {}", err),
+            AppError::Cache(err) => write!(f, "cache error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Database(err) => Some(err),
+            AppError::Cache(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<mongodb::error::Error> for AppError {
+    fn from(err: mongodb::error::Error) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl From<redis::RedisError> for AppError {
+    fn from(err: redis::RedisError) -> Self {
+        AppError::Cache(err)
+    }
+}
+
+impl AppError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AppError::NotFound(_) => ErrorCode::UserNotFound,
+            AppError::Validation(_) => ErrorCode::ValidationFailed,
+            AppError::Duplicate(_) => ErrorCode::EmailExists,
+            AppError::Database(_) | AppError::Cache(_) => ErrorCode::InternalError,
+        }
+    }
+
+    /// Error response carrying the request correlation id, not logged.
+    pub fn response_with_id(&self, request_id: Option<String>) -> HttpResponse {
+        let mut body = ErrorResponse::new(self.code(), self.public_message());
+        body.request_id = request_id;
+        HttpResponse::build(self.status_code()).json(body)
+    }
+
+    /// Message sent to the client.
+    fn public_message(&self) -> String {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Validation(message)
+            | AppError::Duplicate(message) => message.clone(),
+            AppError::Database(_) => "Database error".into(),
+            AppError::Cache(_) => "Cache error".into(),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Duplicate(_) => StatusCode::BAD_REQUEST,
+            AppError::Database(_) | AppError::Cache(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            error!("{}", self);
+        }
+        self.response_with_id(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    async fn body(err: AppError) -> (StatusCode, ErrorResponse) {
+        let res = err.error_response();
+        let status = res.status();
+        let bytes = to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn client_errors_keep_their_message() {
+        let (status, res) = body(AppError::Validation("email is invalid".into())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(res.code, Some(ErrorCode::ValidationFailed));
+        assert_eq!(res.message, "email is invalid");
+
+        let (status, res) = body(AppError::Duplicate("Already exists".into())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(res.code, Some(ErrorCode::EmailExists));
+
+        let (status, res) = body(AppError::NotFound("User not found".into())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(res.code, Some(ErrorCode::UserNotFound));
+    }
+
+    #[actix_web::test]
+    async fn server_errors_hide_their_details() {
+        let err = redis::RedisError::from((redis::ErrorKind::IoError, "secret host 10.0.0.7"));
+        let (status, res) = body(AppError::from(err)).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.code, Some(ErrorCode::InternalError));
+        assert_eq!(res.message, "Cache error");
+    }
+}
@@ -0,0 +1,130 @@
+//! JSON access log middleware, the `LOG_FORMAT=json` alternative to actix `Logger`.
+//!
+//! Each request is logged once, at `info` level with the `access_log` target, as a
+//! single-line JSON object log aggregators can parse without a custom pattern:
+//! `{"method":"GET","path":"/users","status":200,"duration_ms":1.42,"request_id":"..."}`.
+use std::time::Instant;
+
+use actix_web::{
+    Error, HttpMessage,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use log::info;
+use serde_json::json;
+
+use crate::shared::middleware::request_id::RequestId;
+
+/// Log target of the access log lines.
+pub const ACCESS_LOG_TARGET: &str = "access_log";
+
+/// Paths left out of the access log, like the text `Logger` exclusions.
+const EXCLUDED_PATHS: [&str; 1] = ["/favicon.ico"];
+
+/// Access log middleware, wrap it with `middleware::from_fn(json_access_log)` inside
+/// `request_id` so the id is known.
+pub async fn json_access_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if EXCLUDED_PATHS.contains(&req.path()) {
+        return next.call(req).await;
+    }
+
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let path = req.path().to_owned();
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+
+    let res = next.call(req).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(err) => err.as_response_error().status_code(),
+    };
+    info!(
+        target: ACCESS_LOG_TARGET,
+        "{}",
+        json!({
+            "method": method,
+            "path": path,
+            "status": status.as_u16(),
+            "duration_ms": started.elapsed().as_secs_f64() * 1000.0,
+            "request_id": request_id,
+        })
+    );
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Mutex, time::Duration};
+
+    use actix_web::{
+        App, HttpResponse,
+        middleware::from_fn,
+        rt::time,
+        test::{self, TestRequest},
+        web,
+    };
+    use log::{LevelFilter, Log, Metadata, Record};
+    use serde_json::Value;
+
+    use crate::shared::middleware::request_id::{REQUEST_ID_HEADER, request_id};
+
+    /// Records the access log lines, other targets are dropped.
+    struct Capture;
+
+    static LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == ACCESS_LOG_TARGET
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                LINES.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    async fn slow_teapot() -> HttpResponse {
+        time::sleep(Duration::from_millis(20)).await;
+        HttpResponse::ImATeapot().finish()
+    }
+
+    #[actix_web::test]
+    async fn requests_are_logged_as_one_json_line() {
+        let _ = log::set_logger(&Capture);
+        log::set_max_level(LevelFilter::Info);
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(json_access_log))
+                .wrap(from_fn(request_id))
+                .route("/teapot", web::get().to(slow_teapot)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/teapot")
+            .insert_header((REQUEST_ID_HEADER, "log-123"))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let line: Value = LINES
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .find(|line| line["request_id"] == "log-123")
+            .expect("an access log line for the request");
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["path"], "/teapot");
+        assert_eq!(line["status"], 418);
+        assert!(line["duration_ms"].as_f64().unwrap() >= 20.0, "{}", line);
+    }
+}
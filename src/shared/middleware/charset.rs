@@ -0,0 +1,129 @@
+//! UTF-8 charset middleware.
+//!
+//! Request bodies are parsed as UTF-8 JSON, MongoDB stores UTF-8 strings and Handlebars
+//! renders UTF-8, so text round-trips unchanged end to end (`José 李` included). This
+//! middleware makes the encoding explicit on the way out: `application/json` and
+//! `text/*` responses without a charset get `; charset=utf-8`.
+//!
+//! With `ACCEPT_CHARSET_STRICT`, a request whose `Accept-Charset` header rules out UTF-8
+//! is answered with 406, the only charset this server produces.
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        StatusCode,
+        header::{self, HeaderValue},
+    },
+    middleware::Next,
+    web,
+};
+
+use crate::shared::{
+    config::settings::Settings,
+    dto::response::{ErrorCode, http_error},
+};
+
+/// Charset middleware, wrap it with `middleware::from_fn(utf8_charset)`.
+pub async fn utf8_charset(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let strict = req
+        .app_data::<web::Data<Settings>>()
+        .is_some_and(|config| config.accept_charset_strict);
+
+    if strict
+        && let Some(accept) = req
+            .headers()
+            .get(header::ACCEPT_CHARSET)
+            .and_then(|v| v.to_str().ok())
+        && !accepts_utf8(accept)
+    {
+        return Ok(req.into_response(http_error(
+            StatusCode::NOT_ACCEPTABLE,
+            ErrorCode::NotAcceptable,
+            "Only utf-8 responses are available".into(),
+        )));
+    }
+
+    let mut res = next.call(req).await?.map_into_boxed_body();
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| needs_charset(v))
+        .map(|v| format!("{}; charset=utf-8", v));
+    if let Some(value) = content_type.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        res.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    Ok(res)
+}
+
+/// Textual media type without an explicit charset.
+fn needs_charset(content_type: &str) -> bool {
+    let lower = content_type.to_ascii_lowercase();
+    (lower.starts_with("application/json") || lower.starts_with("text/"))
+        && !lower.contains("charset=")
+}
+
+/// Whether an `Accept-Charset` value allows utf-8 (`utf-8` or `*` with a non zero weight).
+fn accepts_utf8(accept: &str) -> bool {
+    accept.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        let charset = parts.next().unwrap_or_default();
+        let rejected = parts.any(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        (charset.eq_ignore_ascii_case("utf-8") || charset == "*") && !rejected
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_and_wildcard_are_accepted() {
+        assert!(accepts_utf8("utf-8"));
+        assert!(accepts_utf8("UTF-8;q=0.5"));
+        assert!(accepts_utf8("*"));
+        assert!(accepts_utf8("iso-8859-1, utf-8;q=0.7"));
+    }
+
+    #[test]
+    fn zero_weight_and_other_charsets_are_rejected() {
+        assert!(!accepts_utf8("utf-8;q=0"));
+        assert!(!accepts_utf8("iso-8859-1"));
+    }
+
+    #[actix_web::test]
+    async fn strict_mode_answers_406() {
+        use crate::shared::dto::response::ErrorResponse;
+        use actix_web::{App, HttpResponse, middleware::from_fn, test};
+
+        let config = Settings {
+            accept_charset_strict: true,
+            ..Settings::defaults()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .wrap(from_fn(utf8_charset))
+                .route("/users", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/users")
+            .insert_header((header::ACCEPT_CHARSET, "iso-8859-1"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+        let body: ErrorResponse = test::read_body_json(res).await;
+        assert_eq!(body.code, Some(ErrorCode::NotAcceptable));
+    }
+}
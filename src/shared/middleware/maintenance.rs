@@ -0,0 +1,80 @@
+//! Maintenance mode middleware.
+//!
+//! While maintenance mode is on, every route except `/health`, `/.well-known/health` and
+//! `/admin` is answered with 503 and a `Retry-After` header, so deploys and data migrations
+//! can run without serving traffic. `/health/live` keeps answering 200 (the process is alive) while
+//! `/health/ready` answers 503 so load balancers drain the instance.
+//!
+//! The flag starts from `MAINTENANCE_MODE` and can be toggled at runtime with
+//! `POST /admin/maintenance`.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web,
+};
+
+use crate::shared::dto::response::http_service_unavailable;
+
+/// Path prefixes served while in maintenance.
+const EXEMPT_PREFIXES: [&str; 3] = ["/health", "/.well-known/health", "/admin"];
+
+/// Shared maintenance flag, injected via `web::Data<MaintenanceMode>`.
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    retry_after_secs: u64,
+}
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool, retry_after_secs: u64) -> Self {
+        MaintenanceMode {
+            enabled: AtomicBool::new(enabled),
+            retry_after_secs,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Seconds clients are told to wait (`Retry-After`) before retrying.
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after_secs
+    }
+}
+
+/// `/health`, `/.well-known/health` and `/admin` (and anything below them) stay reachable.
+fn is_exempt(path: &str) -> bool {
+    EXEMPT_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Maintenance middleware, wrap it with `middleware::from_fn(maintenance)`.
+///
+/// Requires `web::Data<MaintenanceMode>`, requests pass through when it is missing.
+pub async fn maintenance(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if let Some(mode) = req.app_data::<web::Data<MaintenanceMode>>()
+        && mode.is_enabled()
+        && !is_exempt(req.path())
+    {
+        let retry_after = mode.retry_after_secs();
+        return Ok(req.into_response(http_service_unavailable(
+            "Service under maintenance".into(),
+            retry_after,
+        )));
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
@@ -0,0 +1,8 @@
+pub mod access_log;
+pub mod charset;
+pub mod maintenance;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+pub mod strict_json;
+pub mod timeout;
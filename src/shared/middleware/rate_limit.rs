@@ -0,0 +1,179 @@
+//! Per-IP rate limiting middleware.
+//!
+//! The backend is selected by `RATE_LIMIT_BACKEND`. The `local` backend keeps an
+//! in-process token bucket per client IP: each bucket holds up to `RATE_LIMIT_BURST`
+//! tokens and regains `RATE_LIMIT_REFILL_PER_SEC` tokens per second. A request takes
+//! one token, an empty bucket is answered with 429 and a `Retry-After` header.
+//!
+//! Buckets live in memory, so limits are per instance: use it for single-instance
+//! deployments. The client IP is the socket peer address.
+//!
+//! The `redis` backend counts the requests of each client IP in fixed windows of
+//! `RATE_LIMIT_WINDOW_SECS`, shared by every instance: past `RATE_LIMIT_MAX` requests
+//! the client waits for the next window. Each window has its own key, `INCR` and `EXPIRE`
+//! run in one transaction so no counter outlives its window. A Redis failure lets the
+//! request through, an outage never blocks the API.
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Mutex, PoisonError},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web,
+};
+use log::warn;
+use redis::aio::ConnectionManager;
+
+use crate::shared::{
+    config::settings::{RateLimitBackend, RedisKeys, Settings},
+    dto::response::http_too_many_requests,
+};
+
+/// Number of tracked clients above which full (idle) buckets are evicted.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// In-process token bucket rate limiter keyed by client IP.
+pub struct LocalRateLimiter {
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+    burst: f64,
+    refill_per_sec: f64,
+}
+
+impl LocalRateLimiter {
+    pub fn new(burst: u32, refill_per_sec: f64) -> Self {
+        LocalRateLimiter {
+            buckets: Mutex::new(HashMap::new()),
+            burst: f64::from(burst),
+            refill_per_sec,
+        }
+    }
+
+    /// Takes one token from the bucket of `ip`.
+    ///
+    /// Returns `Err(retry_after_secs)` when the bucket is empty.
+    pub fn try_acquire(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if buckets.len() > PRUNE_THRESHOLD {
+            let (burst, refill_per_sec) = (self.burst, self.refill_per_sec);
+            buckets.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + elapsed * refill_per_sec < burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(TokenBucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err((missing / self.refill_per_sec).ceil() as u64)
+        }
+    }
+}
+
+/// Number of the fixed window holding `now_secs` and the seconds left until the next one.
+fn fixed_window(now_secs: u64, window_secs: u64) -> (u64, u64) {
+    (now_secs / window_secs, window_secs - now_secs % window_secs)
+}
+
+/// Counts one request of `ip` in the current Redis window.
+///
+/// Returns `Err(retry_after_secs)` past `RATE_LIMIT_MAX` requests, `Ok` as well when Redis
+/// fails.
+async fn redis_acquire(
+    redis: &ConnectionManager,
+    config: &Settings,
+    ip: IpAddr,
+) -> Result<(), u64> {
+    let limits = &config.rate_limit;
+    let now_secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (window, retry_after) = fixed_window(now_secs, limits.window_secs);
+    let ip = ip.to_string();
+    let key = RedisKeys::RateLimit { ip: &ip, window }.key(&config.redis_key_prefix);
+
+    let mut conn = redis.clone();
+    let counted: Result<(u64,), _> = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .expire(&key, i64::try_from(limits.window_secs).unwrap_or(i64::MAX))
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    match counted {
+        Ok((count,)) if count > u64::from(limits.max) => Err(retry_after),
+        Ok(_) => Ok(()),
+        Err(err) => {
+            warn!("Rate limiter unavailable, request let through: {}", err);
+            Ok(())
+        }
+    }
+}
+
+/// Rate limiting middleware, wrap it with `middleware::from_fn(rate_limit)`.
+///
+/// Requires `web::Data<Settings>` and, for the local backend, `web::Data<LocalRateLimiter>`,
+/// for the redis backend, `web::Data<ConnectionManager>`.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let config = req.app_data::<web::Data<Settings>>().cloned();
+    let backend = config
+        .as_ref()
+        .map(|config| config.rate_limit.backend)
+        .unwrap_or(RateLimitBackend::Disabled);
+
+    let limited = match (backend, req.peer_addr()) {
+        (RateLimitBackend::Local, Some(addr)) => req
+            .app_data::<web::Data<LocalRateLimiter>>()
+            .and_then(|limiter| limiter.try_acquire(addr.ip()).err()),
+        (RateLimitBackend::Redis, Some(addr)) => {
+            match (req.app_data::<web::Data<ConnectionManager>>(), &config) {
+                (Some(redis), Some(config)) => redis_acquire(redis, config, addr.ip()).await.err(),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    if let Some(retry_after) = limited {
+        return Ok(req.into_response(http_too_many_requests(retry_after)));
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_window_retry_after_reaches_the_next_window() {
+        assert_eq!(fixed_window(120, 60), (2, 60));
+        assert_eq!(fixed_window(121, 60), (2, 59));
+        assert_eq!(fixed_window(179, 60), (2, 1));
+        assert_eq!(fixed_window(7, 1), (7, 1));
+    }
+}
@@ -0,0 +1,148 @@
+//! Request correlation id middleware.
+//!
+//! Every request gets an id: the incoming correlation header when present and sane,
+//! a generated UUID v4 otherwise. The id is stored in the request extensions and echoed
+//! back in the same response header. `AppError` responses also carry it in the
+//! `request_id` field of their body. Handlers read it with [`request_id_of`].
+//!
+//! The header is `X-Request-Id` unless `REQUEST_ID_HEADER` names another one (e.g.
+//! `X-Correlation-Id` or `traceparent`), see `Settings::request_id_header`.
+use actix_web::{
+    Error, HttpMessage, HttpRequest,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    web,
+};
+use uuid::Uuid;
+
+use crate::shared::{config::settings::Settings, error::AppError};
+
+/// Default correlation id header.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Incoming ids longer than this are replaced by a generated one.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Correlation id of the current request, stored in the request extensions.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Request id middleware, wrap it with `middleware::from_fn(request_id)`.
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let header = req
+        .app_data::<web::Data<Settings>>()
+        .and_then(|config| HeaderName::from_bytes(config.request_id_header.as_bytes()).ok())
+        .unwrap_or(HeaderName::from_static(REQUEST_ID_HEADER));
+    let id = req
+        .headers()
+        .get(&header)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid_request_id(v))
+        .map(str::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut res = next.call(req).await?.map_into_boxed_body();
+    // the body is rebuilt, the error was already logged by `AppError::error_response`
+    let app_error_response = res
+        .response()
+        .error()
+        .and_then(|err| err.as_error::<AppError>())
+        .map(|err| err.response_with_id(Some(id.clone())));
+    if let Some(mut response) = app_error_response {
+        // keep the headers set by inner middleware (CORS, security headers, `Vary`...)
+        let own: Vec<HeaderName> = response.headers().keys().cloned().collect();
+        for (name, value) in res.headers().iter() {
+            if !own.contains(name) {
+                response.headers_mut().append(name.clone(), value.clone());
+            }
+        }
+        res = res.into_response(response);
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(header, value);
+    }
+    Ok(res)
+}
+
+/// Returns the id of the request, `None` when the middleware is not installed.
+pub fn request_id_of(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<RequestId>().map(|id| id.0.clone())
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.chars().all(|c| c.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        App, HttpResponse,
+        middleware::{DefaultHeaders, from_fn},
+        test::{self, TestRequest},
+    };
+
+    use crate::shared::dto::response::ErrorResponse;
+
+    async fn missing() -> Result<HttpResponse, AppError> {
+        Err(AppError::NotFound("User not found".into()))
+    }
+
+    #[actix_web::test]
+    async fn app_errors_carry_the_request_id() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(request_id))
+                .route("/missing", web::get().to(missing)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/missing")
+            .insert_header((REQUEST_ID_HEADER, "abc-123"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "abc-123");
+        let body: ErrorResponse = test::read_body_json(res).await;
+        assert_eq!(body.request_id.as_deref(), Some("abc-123"));
+        assert_eq!(body.message, "User not found");
+
+        // a generated id is echoed in the header and the body alike
+        let res = test::call_service(&app, TestRequest::get().uri("/missing").to_request()).await;
+        let header = res.headers().get(REQUEST_ID_HEADER).unwrap().clone();
+        let body: ErrorResponse = test::read_body_json(res).await;
+        assert_eq!(body.request_id.as_deref(), header.to_str().ok());
+    }
+
+    #[actix_web::test]
+    async fn app_errors_keep_inner_middleware_headers() {
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    DefaultHeaders::new()
+                        .add(("x-frame-options", "DENY"))
+                        .add(("vary", "accept-encoding")),
+                )
+                .wrap(from_fn(request_id))
+                .route("/missing", web::get().to(missing)),
+        )
+        .await;
+
+        let res = test::call_service(&app, TestRequest::get().uri("/missing").to_request()).await;
+        assert_eq!(res.status(), 404);
+        assert_eq!(res.headers().get("x-frame-options").unwrap(), "DENY");
+        assert_eq!(res.headers().get("vary").unwrap(), "accept-encoding");
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        assert!(res.headers().contains_key(REQUEST_ID_HEADER));
+    }
+}
@@ -0,0 +1,118 @@
+//! Security headers middleware.
+//!
+//! Every response gets `X-Content-Type-Options: nosniff`, `X-Frame-Options: DENY` and
+//! `Referrer-Policy: no-referrer`, plus the `Content-Security-Policy` of `CSP_HEADER`
+//! (see `Settings::csp_header`). Headers already set by a handler are left untouched.
+//!
+//! The CSP is skipped under [`CSP_SKIPPED_PREFIXES`]: static assets are not documents a
+//! policy protects, and Swagger UI relies on inline styles and `data:` images.
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderName, HeaderValue},
+    middleware::Next,
+    web,
+};
+
+use crate::shared::config::settings::Settings;
+
+/// Path prefixes served without `Content-Security-Policy`.
+pub const CSP_SKIPPED_PREFIXES: [&str; 2] = ["/assets", "/swagger-ui"];
+
+const STATIC_HEADERS: [(HeaderName, &str); 3] = [
+    (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+    (header::X_FRAME_OPTIONS, "DENY"),
+    (header::REFERRER_POLICY, "no-referrer"),
+];
+
+/// Security headers middleware, wrap it with `middleware::from_fn(security_headers)`.
+pub async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let csp = req
+        .app_data::<web::Data<Settings>>()
+        .filter(|_| !is_csp_skipped(req.path()))
+        .and_then(|config| HeaderValue::from_str(&config.csp_header).ok());
+
+    let mut res = next.call(req).await?.map_into_boxed_body();
+    let headers = res.headers_mut();
+    for (name, value) in STATIC_HEADERS {
+        if !headers.contains_key(&name) {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+    }
+    if let Some(csp) = csp
+        && !headers.contains_key(header::CONTENT_SECURITY_POLICY)
+    {
+        headers.insert(header::CONTENT_SECURITY_POLICY, csp);
+    }
+    Ok(res)
+}
+
+/// Whether `path` is under one of [`CSP_SKIPPED_PREFIXES`].
+fn is_csp_skipped(path: &str) -> bool {
+    CSP_SKIPPED_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        App, HttpResponse,
+        middleware::from_fn,
+        test::{self, TestRequest},
+    };
+
+    #[actix_web::test]
+    async fn headers_are_set_and_csp_skipped_on_assets() {
+        let mut config = Settings::defaults();
+        config.csp_header = "default-src 'self'".into();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .wrap(from_fn(security_headers))
+                .route("/", web::get().to(HttpResponse::Ok))
+                .route("/assets/css/style.css", web::get().to(HttpResponse::Ok))
+                .route(
+                    "/framed",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .insert_header((header::X_FRAME_OPTIONS, "SAMEORIGIN"))
+                            .finish()
+                    }),
+                ),
+        )
+        .await;
+
+        let res = test::call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        let headers = res.headers();
+        assert_eq!(
+            headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+        assert_eq!(headers.get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "no-referrer");
+        assert_eq!(
+            headers.get(header::CONTENT_SECURITY_POLICY).unwrap(),
+            "default-src 'self'"
+        );
+
+        let req = TestRequest::get().uri("/assets/css/style.css").to_request();
+        let res = test::call_service(&app, req).await;
+        assert!(res.headers().contains_key(header::X_CONTENT_TYPE_OPTIONS));
+        assert!(!res.headers().contains_key(header::CONTENT_SECURITY_POLICY));
+
+        // a header set by the handler wins
+        let res = test::call_service(&app, TestRequest::get().uri("/framed").to_request()).await;
+        assert_eq!(
+            res.headers().get(header::X_FRAME_OPTIONS).unwrap(),
+            "SAMEORIGIN"
+        );
+        assert!(!is_csp_skipped("/assetsfoo"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Duplicate JSON key detection middleware.
+//!
+//! serde_json keeps the last value of a repeated key, so `{"email": "a", "email": "b"}`
+//! is silently read as `b`. That can hide client bugs or be used to smuggle a value past
+//! a proxy that reads the first one. With `STRICT_JSON_KEYS`, JSON request bodies with a
+//! repeated key in any object are rejected with 400 before reaching the handlers.
+//!
+//! Malformed bodies are passed through untouched, the `Json` extractor reports them.
+//! Bodies over `MAX_JSON_BYTES` answer 413 (`PAYLOAD_TOO_LARGE`) as from the extractor.
+use std::{cell::RefCell, collections::HashSet, fmt};
+
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::PayloadError,
+    http::{StatusCode, header},
+    middleware::Next,
+    web,
+};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+
+use crate::shared::{
+    config::settings::Settings,
+    dto::response::{ErrorCode, http_error},
+};
+
+/// Strict JSON middleware, wrap it with `middleware::from_fn(strict_json)`.
+pub async fn strict_json(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let strict = req
+        .app_data::<web::Data<Settings>>()
+        .is_some_and(|config| config.strict_json_keys);
+    let is_json = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().starts_with("application/json"));
+
+    if strict && is_json {
+        // read under the app `PayloadConfig`, same limit as the `Json` extractor
+        let body = match req.extract::<web::Bytes>().await {
+            Ok(body) => body,
+            Err(err) if matches!(err.as_error(), Some(PayloadError::Overflow)) => {
+                return Ok(req.into_response(http_error(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    ErrorCode::PayloadTooLarge,
+                    "JSON body too large".into(),
+                )));
+            }
+            Err(err) => return Err(err),
+        };
+        if let Err(key) = check_duplicate_keys(&body) {
+            return Ok(req.into_response(http_error(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::ValidationFailed,
+                format!("Duplicate JSON key {}", key),
+            )));
+        }
+        // Put the consumed body back for the handler extractors
+        req.set_payload(Payload::from(body));
+    }
+
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+/// Returns the first repeated key found in any object of `body`.
+///
+/// Bodies that are not valid JSON are accepted here.
+pub fn check_duplicate_keys(body: &[u8]) -> Result<(), String> {
+    let duplicate = RefCell::new(None);
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    // Syntax errors are left to the extractors, only a recorded duplicate matters
+    let _ = UniqueKeys(&duplicate).deserialize(&mut deserializer);
+
+    match duplicate.into_inner() {
+        Some(key) => Err(key),
+        None => Ok(()),
+    }
+}
+
+/// Walks any JSON value, records the first repeated object key and stops there.
+#[derive(Clone, Copy)]
+struct UniqueKeys<'a>(&'a RefCell<Option<String>>);
+
+impl<'de> DeserializeSeed<'de> for UniqueKeys<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for UniqueKeys<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut keys = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if !keys.insert(key.clone()) {
+                *self.0.borrow_mut() = Some(key);
+                return Err(de::Error::custom("duplicate key"));
+            }
+            map.next_value_seed(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(self)?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_keys_pass() {
+        assert_eq!(
+            check_duplicate_keys(br#"{"email": "a@b.c", "age": 3}"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn top_level_duplicate_is_reported() {
+        assert_eq!(
+            check_duplicate_keys(br#"{"email": "a@b.c", "email": "x@y.z"}"#),
+            Err("email".into())
+        );
+    }
+
+    #[test]
+    fn nested_and_array_duplicates_are_reported() {
+        assert_eq!(
+            check_duplicate_keys(br#"{"set": {"age": 1, "age": 2}}"#),
+            Err("age".into())
+        );
+        assert_eq!(
+            check_duplicate_keys(br#"[{"a": 1}, {"b": 1, "b": 2}]"#),
+            Err("b".into())
+        );
+    }
+
+    #[test]
+    fn same_key_in_sibling_objects_is_allowed() {
+        assert_eq!(
+            check_duplicate_keys(br#"[{"email": "a@b.c"}, {"email": "x@y.z"}]"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn escaped_keys_are_compared_decoded() {
+        assert_eq!(
+            check_duplicate_keys(br#"{"email": 1, "\u0065mail": 2}"#),
+            Err("email".into())
+        );
+    }
+
+    #[test]
+    fn malformed_bodies_are_left_to_the_extractors() {
+        assert_eq!(check_duplicate_keys(br#"{"email": "#), Ok(()));
+        assert_eq!(check_duplicate_keys(b""), Ok(()));
+    }
+
+    #[actix_web::test]
+    async fn oversized_bodies_use_the_error_shape() {
+        use crate::shared::dto::response::ErrorResponse;
+        use actix_web::{App, HttpResponse, middleware::from_fn, test};
+
+        let config = Settings {
+            strict_json_keys: true,
+            ..Settings::defaults()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::PayloadConfig::new(32))
+                .wrap(from_fn(strict_json))
+                .route("/users", web::post().to(HttpResponse::Created)),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(format!(r#"{{"first_name": "{}"}}"#, "x".repeat(64)))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: ErrorResponse = test::read_body_json(res).await;
+        assert_eq!(body.code, Some(ErrorCode::PayloadTooLarge));
+    }
+}
@@ -0,0 +1,117 @@
+//! Request deadline middleware.
+//!
+//! A request still being handled after its deadline is dropped (pending database calls
+//! included) and answered with 504. The deadline comes from `REQUEST_TIMEOUT_MS`, routes
+//! can override it through `REQUEST_TIMEOUT_OVERRIDES` keyed by route pattern (see
+//! `TimeoutConfig::for_route`), so slow but legitimate endpoints coexist with tight
+//! deadlines elsewhere.
+//!
+//! The deadline covers producing the response, not sending it: a streamed body (e.g.
+//! `GET /users/export.zip`) is not cut once its headers are out. The 504 is an ordinary
+//! response, outer middlewares (request id, logs, security headers, CORS) handle it.
+//!
+//! The middleware wraps resources, not the app: the 504 needs the request, which can only
+//! be cloned once routing is done. Handlers declare it with `wrap = "from_fn(timeout)"`.
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    rt::time,
+    web,
+};
+use log::warn;
+
+use crate::shared::{
+    config::settings::Settings, dto::response::http_gateway_timeout,
+    middleware::request_id::request_id_of,
+};
+
+/// Timeout middleware, wrap it with `middleware::from_fn(timeout)`.
+///
+/// Requires `web::Data<Settings>`, requests have no deadline when it is missing. Register
+/// it on resources (e.g. `#[get("", wrap = "from_fn(timeout)")]`), never on an app or a
+/// scope: cloning the request before routing makes the router panic.
+pub async fn timeout(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let route = req.match_pattern().unwrap_or_else(|| req.path().to_owned());
+    let deadline = req
+        .app_data::<web::Data<Settings>>()
+        .and_then(|config| config.request_timeout.for_route(&route));
+    let Some(deadline) = deadline else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    // routed already, the clone outlives the cancelled handler to answer the 504
+    let request = req.request().clone();
+
+    match time::timeout(deadline, next.call(req)).await {
+        Ok(res) => Ok(res?.map_into_boxed_body()),
+        Err(_) => {
+            warn!(
+                "Request to {} exceeded its {}ms deadline",
+                route,
+                deadline.as_millis()
+            );
+            let res = http_gateway_timeout("Request timed out".into(), request_id_of(&request));
+            Ok(ServiceResponse::new(request, res))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        App, HttpResponse,
+        http::StatusCode,
+        middleware::from_fn,
+        test::{self, TestRequest},
+    };
+    use std::time::Duration;
+
+    use crate::shared::{
+        dto::response::{ErrorCode, ErrorResponse},
+        middleware::{
+            request_id::{REQUEST_ID_HEADER, request_id},
+            security_headers::security_headers,
+        },
+    };
+
+    async fn slow() -> HttpResponse {
+        time::sleep(Duration::from_secs(5)).await;
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn timeouts_answer_504_with_the_request_id() {
+        let mut config = Settings::defaults();
+        config.request_timeout.default_ms = 20;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .wrap(from_fn(security_headers))
+                .wrap(from_fn(request_id))
+                .service(
+                    web::resource("/slow")
+                        .wrap(from_fn(timeout))
+                        .route(web::get().to(slow)),
+                ),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/slow")
+            .insert_header((REQUEST_ID_HEADER, "abc-123"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        // a response like any other, outer middlewares add their headers
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "abc-123");
+        assert_eq!(res.headers().get("x-frame-options").unwrap(), "DENY");
+        let body: ErrorResponse = test::read_body_json(res).await;
+        assert_eq!(body.code, Some(ErrorCode::Timeout));
+        assert_eq!(body.request_id.as_deref(), Some("abc-123"));
+    }
+}
@@ -1,2 +1,9 @@
+pub mod auth;
+pub mod cache;
+pub mod concurrency;
 pub mod config;
 pub mod dto;
+pub mod error;
+pub mod middleware;
+pub mod tasks;
+pub mod template;
@@ -0,0 +1,3 @@
+pub mod redis_keepalive;
+pub mod shutdown;
+pub mod supervisor;
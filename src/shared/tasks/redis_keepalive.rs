@@ -0,0 +1,42 @@
+//! Redis keep-alive pings.
+//!
+//! Load balancers and proxies in front of Redis may silently drop idle connections. The
+//! `ConnectionManager` only notices on the next command, so the first request after a
+//! quiet period would pay for the reconnect. A periodic `PING` keeps the connection warm
+//! and lets the manager reconnect in the background instead.
+use std::time::Duration;
+
+use actix_web::rt::time::interval;
+use log::{debug, info, warn};
+use redis::aio::ConnectionManager;
+
+use crate::shared::tasks::shutdown::ShutdownSignal;
+
+/// Sends `PING` every `period` until shutdown, start it with `spawn_supervised`.
+///
+/// Failed pings are logged once per outage, the next successful ping logs the reconnection.
+/// A ping in flight when shutdown is signaled completes first.
+pub async fn redis_keepalive(
+    redis: ConnectionManager,
+    period: Duration,
+    mut shutdown: ShutdownSignal,
+) {
+    let mut redis = redis;
+    let mut ticker = interval(period);
+    let mut failing = false;
+
+    while shutdown.run_until(ticker.tick()).await.is_some() {
+        match redis::cmd("PING").query_async::<String>(&mut redis).await {
+            Ok(_) if failing => {
+                info!("Redis keep-alive: connection re-established");
+                failing = false;
+            }
+            Ok(_) => debug!("Redis keep-alive: PONG"),
+            Err(err) if !failing => {
+                warn!("Redis keep-alive: ping failed, reconnecting: {}", err);
+                failing = true;
+            }
+            Err(err) => debug!("Redis keep-alive: still unreachable: {}", err),
+        }
+    }
+}
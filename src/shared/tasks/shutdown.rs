@@ -0,0 +1,164 @@
+//! Graceful shutdown of background tasks.
+//!
+//! Once the HTTP server has stopped, `main` calls [`Shutdown::drain`]: every background
+//! task is signaled to stop, then awaited up to `SHUTDOWN_DRAIN_SECS` so in-flight work
+//! (a ping, a flush) completes instead of being dropped mid-way. Tasks still running at
+//! the deadline are aborted and reported.
+//!
+//! Tasks observe the signal through the [`ShutdownSignal`] handed to them by
+//! `spawn_supervised`, typically by racing their waits with [`ShutdownSignal::run_until`].
+//!
+//! The shutdown itself starts on [`termination_signal`]: `main` then stops the HTTP server
+//! gracefully, letting in-flight requests complete, before draining the tasks.
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use actix_web::rt::{
+    task::JoinHandle,
+    time::{Instant, timeout},
+};
+use futures_util::future::{Either, select};
+use log::{info, warn};
+use tokio::sync::watch;
+
+/// Resolves on `SIGTERM` or `SIGINT` (Ctrl-C), with the name of the signal received.
+pub async fn termination_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler");
+        tokio::select! {
+            _ = terminate.recv() => "SIGTERM",
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
+/// Shutdown coordinator, owns the signal and the handles of the tasks to drain.
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+    tasks: Mutex<Vec<(&'static str, JoinHandle<()>)>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown {
+            sender: watch::Sender::new(false),
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// New receiver of the shutdown signal.
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal(self.sender.subscribe())
+    }
+
+    /// Registers a task awaited by [`Shutdown::drain`].
+    pub fn track(&self, name: &'static str, handle: JoinHandle<()>) {
+        self.tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((name, handle));
+    }
+
+    /// Signals every task to stop and waits for them, at most `timeout_after` in total.
+    pub async fn drain(&self, timeout_after: Duration) {
+        self.sender.send_replace(true);
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap_or_else(PoisonError::into_inner));
+        if tasks.is_empty() {
+            return;
+        }
+
+        info!(
+            "Draining {} background task(s), up to {:?}",
+            tasks.len(),
+            timeout_after
+        );
+        let deadline = Instant::now() + timeout_after;
+        for (name, mut handle) in tasks {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if timeout(remaining, &mut handle).await.is_err() {
+                warn!(
+                    "Background task {} still running after {:?}, aborting it",
+                    name, timeout_after
+                );
+                handle.abort();
+            }
+        }
+    }
+}
+
+/// Receiver side of the shutdown signal, cheap to clone.
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once shutdown is requested (immediately if it already was).
+    pub async fn wait(&mut self) {
+        // An error means the coordinator is gone, which only happens at exit
+        let _ = self.0.wait_for(|stop| *stop).await;
+    }
+
+    /// Runs `fut` until it completes, `None` when shutdown is requested first.
+    pub async fn run_until<F: Future>(&mut self, fut: F) -> Option<F::Output> {
+        if self.is_shutdown() {
+            return None;
+        }
+        match select(pin!(fut), pin!(self.wait())).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::rt::{self, time::sleep};
+
+    #[actix_web::test]
+    async fn drain_stops_observing_tasks_and_aborts_the_others() {
+        let shutdown = Shutdown::new();
+
+        let mut signal = shutdown.signal();
+        let observing = rt::spawn(async move {
+            while signal
+                .run_until(sleep(Duration::from_millis(10)))
+                .await
+                .is_some()
+            {}
+        });
+        let stubborn = rt::spawn(sleep(Duration::from_secs(3600)));
+        let stubborn_abort = stubborn.abort_handle();
+        shutdown.track("observing", observing);
+        shutdown.track("stubborn", stubborn);
+
+        let started = Instant::now();
+        shutdown.drain(Duration::from_millis(100)).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(shutdown.signal().is_shutdown());
+        // Aborted tasks finish on their next poll
+        rt::task::yield_now().await;
+        assert!(stubborn_abort.is_finished());
+    }
+}
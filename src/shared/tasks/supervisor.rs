@@ -0,0 +1,82 @@
+//! Supervision of long-running background tasks.
+//!
+//! Request handler panics are recovered by the `CatchPanic` middleware, but a panic in a
+//! spawned background task (scheduler, watcher, pinger, ...) would silently kill it.
+//! Every background task must be started with [`spawn_supervised`] instead of a bare spawn,
+//! which also registers it for draining at shutdown (see `shutdown`).
+use std::{any::Any, future::Future, time::Duration};
+
+use actix_web::rt::{self, time::Instant};
+use log::{error, info, warn};
+
+use crate::shared::{
+    config::settings::TaskRestartConfig,
+    tasks::shutdown::{Shutdown, ShutdownSignal},
+};
+
+/// Runs longer than this reset the restart backoff to its initial value.
+const HEALTHY_RUN: Duration = Duration::from_secs(300);
+
+/// Spawns a supervised background task on the current Actix runtime.
+///
+/// `factory` builds a fresh task future for every (re)start, given the shutdown signal:
+/// the task must return once it fires. When the task panics the panic is logged with the
+/// task `name` and the task is restarted after an exponential backoff bounded by `policy`.
+/// A task that returns normally, or panics during shutdown, is not restarted.
+pub fn spawn_supervised<F, Fut>(
+    name: &'static str,
+    policy: TaskRestartConfig,
+    shutdown: &Shutdown,
+    factory: F,
+) where
+    F: Fn(ShutdownSignal) -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    let initial_backoff = Duration::from_millis(policy.backoff_ms);
+    let max_backoff = Duration::from_millis(policy.max_backoff_ms);
+    let mut signal = shutdown.signal();
+
+    let handle = rt::spawn(async move {
+        let mut backoff = initial_backoff;
+        loop {
+            let started = Instant::now();
+            match rt::spawn(factory(signal.clone())).await {
+                Ok(()) => {
+                    info!("Background task {} finished", name);
+                    break;
+                }
+                Err(err) if err.is_panic() => {
+                    if started.elapsed() >= HEALTHY_RUN {
+                        backoff = initial_backoff;
+                    }
+                    error!(
+                        "Background task {} panicked: {}; restarting in {:?}",
+                        name,
+                        panic_message(err.into_panic()),
+                        backoff
+                    );
+                    if signal.run_until(rt::time::sleep(backoff)).await.is_none() {
+                        info!("Background task {} not restarted, shutting down", name);
+                        break;
+                    }
+                    backoff = (backoff * 2).min(max_backoff);
+                }
+                Err(err) => {
+                    warn!("Background task {} cancelled: {}", name, err);
+                    break;
+                }
+            }
+        }
+    });
+    shutdown.track(name, handle);
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".into()
+    }
+}
@@ -0,0 +1,101 @@
+//! Handlebars helpers available in every template.
+//!
+//! Registered by `build_handlebars` before the templates, next to the built-in ones:
+//!
+//! ```handlebars
+//! {{date_format first_hit "[day]/[month]/[year] [hour]:[minute]"}}
+//! {{uppercase title}}
+//! ```
+//!
+//! Helper output is HTML-escaped like any `{{expr}}`.
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError, RenderErrorReason,
+    ScopedJson, handlebars_helper,
+};
+use serde_json::Value;
+use time::{
+    OffsetDateTime,
+    format_description::{self, well_known::Iso8601},
+};
+
+/// Registers the helpers of this module on `handlebars`.
+pub fn register_helpers(handlebars: &mut Handlebars) {
+    handlebars.register_helper("date_format", Box::new(DateFormat));
+    handlebars.register_helper("uppercase", Box::new(uppercase));
+}
+
+/// `{{date_format iso pattern}}`: reformats an ISO 8601 datetime.
+///
+/// `pattern` uses the `time` crate format description syntax, e.g. `[year]-[month]-[day]`.
+/// An unparsable datetime or pattern fails the render instead of printing a blank.
+struct DateFormat;
+
+impl HelperDef for DateFormat {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let iso = str_param(h, 0)?;
+        let pattern = str_param(h, 1)?;
+        let datetime = OffsetDateTime::parse(iso, &Iso8601::DEFAULT).map_err(|err| {
+            RenderErrorReason::Other(format!("date_format: invalid datetime {}: {}", iso, err))
+        })?;
+        let description = format_description::parse_borrowed::<1>(pattern).map_err(|err| {
+            RenderErrorReason::Other(format!("date_format: invalid pattern {}: {}", pattern, err))
+        })?;
+        let formatted = datetime
+            .format(&description)
+            .map_err(|err| RenderErrorReason::Other(format!("date_format: {}", err)))?;
+
+        Ok(ScopedJson::Derived(Value::String(formatted)))
+    }
+}
+
+/// Returns the string parameter at `idx` of a `date_format` call.
+fn str_param<'a>(h: &'a Helper, idx: usize) -> Result<&'a str, RenderErrorReason> {
+    h.param(idx)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("date_format", idx))?
+        .value()
+        .as_str()
+        .ok_or(RenderErrorReason::InvalidParamType("string"))
+}
+
+// `{{uppercase value}}`: the string value in upper case.
+handlebars_helper!(uppercase: |value: str| value.to_uppercase());
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(template: &str, data: Value) -> Result<String, RenderError> {
+        let mut handlebars = Handlebars::new();
+        register_helpers(&mut handlebars);
+        handlebars.render_template(template, &data)
+    }
+
+    #[test]
+    fn date_format_reformats_iso_datetimes() {
+        let rendered = render(
+            r#"{{date_format at "[day]/[month]/[year] [hour]:[minute]"}}"#,
+            json!({ "at": "2026-10-16T08:05:00.123Z" }),
+        );
+        assert_eq!(rendered.unwrap(), "16/10/2026 08:05");
+
+        let invalid = render(
+            r#"{{date_format at "[year]"}}"#,
+            json!({ "at": "yesterday" }),
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn uppercase_escapes_its_output() {
+        let rendered = render("{{uppercase name}}", json!({ "name": "ada <b>" }));
+        assert_eq!(rendered.unwrap(), "ADA &lt;B&gt;");
+    }
+}
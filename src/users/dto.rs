@@ -1,6 +1,78 @@
+use std::sync::LazyLock;
+
+use mongodb::bson::{Bson, DateTime};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::users::users_model::{IndexInfo, ScoredUser, User, UserProfile};
+
+/// Maximum length of `first_name` and `last_name`, in characters.
+pub const NAME_MAX_LEN: usize = 100;
+
+/// Maximum accepted `age`.
+pub const AGE_MAX: u8 = 150;
+
+/// Fields of [`UserDtoResponse`] a list can be restricted to with `?fields=`.
+pub const USER_FIELDS: [&str; 8] = [
+    "id",
+    "first_name",
+    "last_name",
+    "email",
+    "age",
+    "created_at",
+    "updated_at",
+    "deleted_at",
+];
+
+/// Pragmatic subset of RFC 5322: dot-atom local part, `@`, dotted hostname.
+static EMAIL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+@[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?(?:\.[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?)+$",
+    )
+    .expect("valid email regex")
+});
+
+/// Field checks of a request body, run by handlers before any data access.
+pub trait Validate {
+    /// Every failed check, one message per field, `Ok` when the body is valid.
+    fn validate(&self) -> Result<(), Vec<String>>;
+}
+
+fn check_name(field: &str, value: &str, errors: &mut Vec<String>) {
+    if value.trim().is_empty() {
+        errors.push(format!("{} must not be empty", field));
+    } else if value.chars().count() > NAME_MAX_LEN {
+        errors.push(format!(
+            "{} must be at most {} characters",
+            field, NAME_MAX_LEN
+        ));
+    }
+}
+
+/// Checked as normalized (see `users_service::normalize_email`), surrounding spaces and
+/// case don't make an email invalid.
+fn check_email(value: &str, errors: &mut Vec<String>) {
+    if !EMAIL_RE.is_match(value.trim()) {
+        errors.push("email must be a valid email address".into());
+    }
+}
+
+fn check_age(value: Option<u8>, errors: &mut Vec<String>) {
+    if value.is_some_and(|age| age > AGE_MAX) {
+        errors.push(format!("age must be between 0 and {}", AGE_MAX));
+    }
+}
+
+fn into_result(errors: Vec<String>) -> Result<(), Vec<String>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct UserDtoResponse {
     pub id: String,
     pub first_name: String,
@@ -8,30 +80,501 @@ pub struct UserDtoResponse {
     pub email: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub age: Option<u8>,
+    #[serde(with = "crate::shared::dto::datetime")]
+    #[schema(value_type = String, format = DateTime)]
+    pub created_at: DateTime,
+    #[serde(with = "crate::shared::dto::datetime")]
+    #[schema(value_type = String, format = DateTime)]
+    pub updated_at: DateTime,
+    /// Only on soft-deleted users, listed with `include_deleted=true`.
+    #[serde(
+        with = "crate::shared::dto::datetime::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub deleted_at: Option<DateTime>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl From<User> for UserDtoResponse {
+    fn from(user: User) -> Self {
+        UserDtoResponse {
+            id: user._id.to_hex(),
+            created_at: user.created_at(),
+            updated_at: user.updated_at(),
+            deleted_at: user.deleted_at,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            email: user.email,
+            age: user.age,
+        }
+    }
+}
+
+/// User with server-side computed fields, body of `GET /users/{id}/profile`.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct UserProfileResponse {
+    #[serde(flatten)]
+    pub user: UserDtoResponse,
+    /// `first_name last_name`
+    pub full_name: String,
+    /// `unknown`, `minor`, `18-29`, `30-49`, `50-64` or `65+`
+    pub age_group: String,
+    /// Whole days since `created_at`.
+    pub account_age_days: i64,
+}
+
+impl From<UserProfile> for UserProfileResponse {
+    fn from(profile: UserProfile) -> Self {
+        // the aggregation's `created_at` is consumed by the profile, not the flattened user
+        let mut user = profile.user;
+        user.created_at = Some(profile.created_at);
+        UserProfileResponse {
+            user: UserDtoResponse::from(user),
+            full_name: profile.full_name,
+            age_group: profile.age_group,
+            account_age_days: profile.account_age_days,
+        }
+    }
+}
+
+/// Entry of `GET /users/text-search`.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ScoredUserDtoResponse {
+    #[serde(flatten)]
+    pub user: UserDtoResponse,
+    /// Relevance to the query, only comparable within the same search.
+    pub score: f64,
+}
+
+impl From<ScoredUser> for ScoredUserDtoResponse {
+    fn from(found: ScoredUser) -> Self {
+        ScoredUserDtoResponse {
+            user: UserDtoResponse::from(found.user),
+            score: found.score,
+        }
+    }
+}
+
+/// Entry of `GET /users/_indexes`.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct IndexDtoResponse {
+    pub name: String,
+    /// Key specification as stored, e.g. `{"email": 1}` or `{"_fts": "text", "_ftsx": 1}`.
+    #[schema(value_type = Object)]
+    pub key: serde_json::Value,
+    pub unique: bool,
+}
+
+impl From<IndexInfo> for IndexDtoResponse {
+    fn from(index: IndexInfo) -> Self {
+        IndexDtoResponse {
+            name: index.name,
+            key: Bson::Document(index.key).into_relaxed_extjson(),
+            unique: index.unique,
+        }
+    }
+}
+
+/// Query of `GET /users/text-search`.
+#[derive(Serialize, Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct TextSearchQuery {
+    /// Words searched in `first_name` and `last_name` (MongoDB `$search` syntax).
+    pub q: String,
+    pub limit: Option<u64>,
+}
+
+/// Query of `GET /users/search`.
+#[derive(Serialize, Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct SearchQuery {
+    /// Text searched, case-insensitively and literally, in `first_name`, `last_name` and
+    /// `email`.
+    pub q: String,
+    /// Matching users skipped, in `LIST_DEFAULT_SORT` order.
+    pub skip: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct UserIdDtoResponse {
     pub id: String,
+    /// Stored email, only reported when it differs from the submitted one after normalization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct CreateUserDtoRequest {
     pub first_name: String,
     pub last_name: String,
+    /// Normalized (trimmed and lowercased) before being persisted.
     pub email: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub age: Option<u8>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Validate for CreateUserDtoRequest {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        check_name("first_name", &self.first_name, &mut errors);
+        check_name("last_name", &self.last_name, &mut errors);
+        check_email(&self.email, &mut errors);
+        check_age(self.age, &mut errors);
+        into_result(errors)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct UpdateUserDtoRequest {
+    /// Trimmed before being persisted, must not be blank.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub first_name: Option<String>,
+    /// Trimmed before being persisted, must not be blank.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_name: Option<String>,
+    /// Normalized (trimmed and lowercased) before being persisted.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub age: Option<u8>,
 }
+
+impl UpdateUserDtoRequest {
+    /// True when no field is submitted, the update would change nothing.
+    pub fn is_empty(&self) -> bool {
+        self.first_name.is_none()
+            && self.last_name.is_none()
+            && self.email.is_none()
+            && self.age.is_none()
+    }
+
+    /// Trims the submitted names, validate first: a blank name would become empty.
+    pub fn trim_names(&mut self) {
+        for name in [&mut self.first_name, &mut self.last_name]
+            .into_iter()
+            .flatten()
+        {
+            let trimmed = name.trim();
+            if trimmed.len() != name.len() {
+                *name = trimmed.to_owned();
+            }
+        }
+    }
+
+    /// Sets the submitted fields on `user`, as the stored update does.
+    pub fn apply(&self, user: &mut User) {
+        if let Some(first_name) = &self.first_name {
+            user.first_name = first_name.clone();
+        }
+        if let Some(last_name) = &self.last_name {
+            user.last_name = last_name.clone();
+        }
+        if let Some(email) = &self.email {
+            user.email = email.clone();
+        }
+        if self.age.is_some() {
+            user.age = self.age;
+        }
+    }
+
+    /// Submitted fields whose value differs from `user`, in declaration order.
+    ///
+    /// Compares values as given: normalize the email before calling it.
+    pub fn changed_fields(&self, user: &User) -> Vec<String> {
+        [
+            (
+                "first_name",
+                self.first_name
+                    .as_ref()
+                    .is_some_and(|v| *v != user.first_name),
+            ),
+            (
+                "last_name",
+                self.last_name
+                    .as_ref()
+                    .is_some_and(|v| *v != user.last_name),
+            ),
+            (
+                "email",
+                self.email.as_ref().is_some_and(|v| *v != user.email),
+            ),
+            ("age", self.age.is_some_and(|v| Some(v) != user.age)),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| name.to_owned())
+        .collect()
+    }
+}
+
+/// Response of `PATCH /users/{id}`.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct UpdateUserDtoResponse {
+    /// The user after the update.
+    pub user: UserDtoResponse,
+    /// Names of the fields the update changed, empty when it changed nothing.
+    pub changed: Vec<String>,
+}
+
+/// Only the submitted fields are checked, with the same rules as on create: a submitted
+/// name must not be empty or whitespace only.
+impl Validate for UpdateUserDtoRequest {
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if let Some(first_name) = &self.first_name {
+            check_name("first_name", first_name, &mut errors);
+        }
+        if let Some(last_name) = &self.last_name {
+            check_name("last_name", last_name, &mut errors);
+        }
+        if let Some(email) = &self.email {
+            check_email(email, &mut errors);
+        }
+        check_age(self.age, &mut errors);
+        into_result(errors)
+    }
+}
+
+/// Body of `DELETE /users`, `confirm` must match the configured confirmation token.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct BulkDeleteUsersDtoRequest {
+    pub confirm: Option<String>,
+    pub ids: Vec<String>,
+}
+
+/// Query string of `PATCH /users`, every given field must match.
+///
+/// Unknown parameters are rejected, so a typo can never widen the filter.
+#[derive(Serialize, Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(deny_unknown_fields)]
+pub struct UsersFilterQuery {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// Normalized (trimmed and lowercased) before matching.
+    pub email: Option<String>,
+    pub age: Option<u8>,
+    pub min_age: Option<u8>,
+    pub max_age: Option<u8>,
+    /// Also match soft-deleted users, reserved to admins.
+    #[serde(default)]
+    pub include_deleted: bool,
+    /// Comma separated [`USER_FIELDS`] answered by `GET /users`, not a filter: every field
+    /// when unset.
+    pub fields: Option<String>,
+    /// `GET /users` cursor: only users whose id is greater, listed in `_id` order.
+    pub after: Option<String>,
+    /// `GET /users` offset: matching users skipped, can't be combined with `after`.
+    pub skip: Option<u64>,
+    /// `GET /users` page size, every remaining user when unset.
+    pub limit: Option<u64>,
+}
+
+impl UsersFilterQuery {
+    /// True when no field is set, the filter matches every (active) user.
+    pub fn is_empty(&self) -> bool {
+        self.first_name.is_none()
+            && self.last_name.is_none()
+            && self.email.is_none()
+            && self.age.is_none()
+            && self.min_age.is_none()
+            && self.max_age.is_none()
+    }
+
+    /// The first parameter set among those only `GET /users` supports, not filters.
+    pub fn list_only_param(&self) -> Option<&'static str> {
+        [
+            ("fields", self.fields.is_some()),
+            ("after", self.after.is_some()),
+            ("skip", self.skip.is_some()),
+            ("limit", self.limit.is_some()),
+        ]
+        .into_iter()
+        .find_map(|(name, set)| set.then_some(name))
+    }
+
+    /// The `fields` selection, `None` when every field is returned.
+    ///
+    /// Duplicates are dropped, any name outside [`USER_FIELDS`] is an error listing them.
+    pub fn fields(&self) -> Result<Option<Vec<&'static str>>, String> {
+        let Some(raw) = &self.fields else {
+            return Ok(None);
+        };
+        let mut fields = Vec::new();
+        let mut unknown = Vec::new();
+        for name in raw.split(',').map(str::trim) {
+            match USER_FIELDS.iter().find(|field| **field == name) {
+                Some(field) if !fields.contains(field) => fields.push(*field),
+                Some(_) => {}
+                None => unknown.push(name),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(format!(
+                "Unknown fields: {}, allowed: {}",
+                unknown.join(", "),
+                USER_FIELDS.join(", ")
+            ));
+        }
+        Ok(Some(fields))
+    }
+}
+
+/// Body of `PATCH /users`, `confirm` must match the configured confirmation token.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct BulkUpdateUsersDtoRequest {
+    pub confirm: Option<String>,
+    /// Fields applied with `$set` to every matching user.
+    pub set: UpdateUserDtoRequest,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct BulkUpdateDtoResponse {
+    pub matched: u64,
+    pub modified: u64,
+}
+
+/// A `POST /users/bulk` entry that was not created.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct BulkCreateErrorDto {
+    /// Position of the entry in the request array.
+    pub index: usize,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct BulkCreateDtoResponse {
+    /// Ids of the created users, in request order.
+    pub inserted: Vec<String>,
+    /// Entries not created, in request order.
+    pub errors: Vec<BulkCreateErrorDto>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct UsersCountDtoResponse {
+    pub count: u64,
+    /// `false` when `count` is an estimate (unfiltered) or a lower bound (capped).
+    pub exact: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeletedCountDtoResponse {
+    pub deleted: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> CreateUserDtoRequest {
+        CreateUserDtoRequest {
+            first_name: "Ada".into(),
+            last_name: "Lovelace".into(),
+            email: " Ada.Lovelace+test@Example.co.uk ".into(),
+            age: Some(36),
+        }
+    }
+
+    #[test]
+    fn valid_create_passes() {
+        assert!(valid().validate().is_ok());
+        let no_age = CreateUserDtoRequest {
+            age: None,
+            ..valid()
+        };
+        assert!(no_age.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_create_reports_every_field() {
+        let dto = CreateUserDtoRequest {
+            first_name: "  ".into(),
+            last_name: "x".repeat(NAME_MAX_LEN + 1),
+            email: "not an email@".into(),
+            age: Some(151),
+        };
+        assert_eq!(
+            dto.validate().unwrap_err(),
+            [
+                "first_name must not be empty",
+                "last_name must be at most 100 characters",
+                "email must be a valid email address",
+                "age must be between 0 and 150",
+            ]
+        );
+    }
+
+    #[test]
+    fn names_are_measured_in_characters() {
+        let dto = CreateUserDtoRequest {
+            first_name: "é".repeat(NAME_MAX_LEN),
+            ..valid()
+        };
+        assert!(dto.validate().is_ok());
+    }
+
+    #[test]
+    fn rejected_emails() {
+        for email in [
+            "",
+            "ada",
+            "ada@",
+            "@example.com",
+            "ada@example",
+            "ada@-example.com",
+        ] {
+            let dto = CreateUserDtoRequest {
+                email: email.into(),
+                ..valid()
+            };
+            assert!(dto.validate().is_err(), "{:?} accepted", email);
+        }
+    }
+
+    #[test]
+    fn update_checks_submitted_fields_only() {
+        let empty = UpdateUserDtoRequest {
+            first_name: None,
+            last_name: None,
+            email: None,
+            age: None,
+        };
+        assert!(empty.validate().is_ok());
+
+        let dto = UpdateUserDtoRequest {
+            first_name: Some(String::new()),
+            email: Some("nope".into()),
+            ..empty
+        };
+        assert_eq!(dto.validate().unwrap_err().len(), 2);
+
+        let mut dto = UpdateUserDtoRequest {
+            first_name: Some(" Ada\t".into()),
+            last_name: Some("Lovelace".into()),
+            ..empty
+        };
+        assert!(dto.validate().is_ok());
+        dto.trim_names();
+        assert_eq!(dto.first_name.as_deref(), Some("Ada"));
+        assert_eq!(dto.last_name.as_deref(), Some("Lovelace"));
+    }
+
+    #[test]
+    fn fields_are_whitelisted() {
+        let query = |fields: &str| UsersFilterQuery {
+            fields: Some(fields.into()),
+            ..Default::default()
+        };
+        assert_eq!(UsersFilterQuery::default().fields(), Ok(None));
+        assert_eq!(
+            query("id, email,id").fields(),
+            Ok(Some(vec!["id", "email"]))
+        );
+        let err = query("id,_id,password").fields().unwrap_err();
+        assert!(err.starts_with("Unknown fields: _id, password"), "{}", err);
+    }
+}
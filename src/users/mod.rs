@@ -1,5 +1,10 @@
 pub mod dto;
+pub mod users_cache;
 pub mod users_controller;
+pub mod users_export;
+pub mod users_memory_store;
 pub mod users_model;
+pub mod users_render;
 pub mod users_repository;
 pub mod users_service;
+pub mod users_store;
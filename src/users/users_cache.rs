@@ -0,0 +1,80 @@
+//! Redis cache of `GET /users/{id}` responses (`USER_CACHE_TTL_SECS`).
+//!
+//! Entries are keyed by database and id (see `RedisKeys::User`) and expire after the TTL.
+//! Handlers changing a user drop its entry, changes made by filter (`PATCH /users?<filter>`)
+//! or outside the application are visible once the entry expires.
+//!
+//! Redis failures follow `CACHE_FAILURE_POLICY`: failing open a read is a miss and a write is
+//! skipped. Malformed entries are treated as misses and overwritten.
+use log::{error, warn};
+use mongodb::bson::oid::ObjectId;
+use redis::aio::ConnectionManager;
+
+use crate::{
+    shared::{
+        cache::json::{CacheError, apply_policy, delete, get_json, set_json_ex},
+        config::settings::{CacheFailurePolicy, RedisKeys, Settings},
+    },
+    users::{dto::UserDtoResponse, users_store::StoreScope},
+};
+
+/// User cache of one request scope.
+pub struct UserCache<'a> {
+    redis: &'a ConnectionManager,
+    database: &'a str,
+    key_prefix: &'a str,
+    ttl_secs: u64,
+    policy: CacheFailurePolicy,
+}
+
+impl<'a> UserCache<'a> {
+    /// `None` when the cache is disabled or Redis is not configured.
+    pub fn new(
+        redis: Option<&'a ConnectionManager>,
+        config: &'a Settings,
+        scope: &'a StoreScope,
+    ) -> Option<Self> {
+        let redis = redis.filter(|_| config.user_cache_ttl_secs > 0)?;
+        Some(UserCache {
+            redis,
+            database: &scope.database,
+            key_prefix: &config.redis_key_prefix,
+            ttl_secs: config.user_cache_ttl_secs,
+            policy: config.cache_failure_policy,
+        })
+    }
+
+    fn key(&self, id: ObjectId) -> String {
+        let id = id.to_hex();
+        RedisKeys::User {
+            database: self.database,
+            id: &id,
+        }
+        .key(self.key_prefix)
+    }
+
+    /// Cached user `id`, `Ok(None)` on a miss.
+    pub async fn get(&self, id: ObjectId) -> Result<Option<UserDtoResponse>, CacheError> {
+        let key = self.key(id);
+        match apply_policy(get_json(self.redis, &key).await, self.policy, None) {
+            Err(CacheError::Json(err)) => {
+                warn!("Discarding malformed cache entry {}: {}", key, err);
+                Ok(None)
+            }
+            other => other,
+        }
+    }
+
+    pub async fn set(&self, id: ObjectId, user: &UserDtoResponse) -> Result<(), CacheError> {
+        let res = set_json_ex(self.redis, &self.key(id), user, self.ttl_secs).await;
+        apply_policy(res, self.policy, ())
+    }
+
+    /// Drops the entry of user `id`. The change is already stored, a failure is only
+    /// logged: the stale entry lives until it expires.
+    pub async fn invalidate(&self, id: ObjectId) {
+        if let Err(err) = delete(self.redis, &self.key(id)).await {
+            error!("Failed to invalidate cached user {}: {}", id, err);
+        }
+    }
+}
@@ -1,95 +1,640 @@
 use super::dto::UserDtoResponse;
 use crate::{
     shared::{
-        config::settings::DATABASE_NAME,
-        dto::response::{http_bad_request, http_internal_server_error, http_no_content, http_ok},
+        auth::admin::AdminAuth,
+        config::settings::Settings,
+        dto::response::{
+            ErrorCode, ErrorResponse, MultiStatusItem, MultiStatusResponse, http_bad_request,
+            http_created, http_error, http_internal_server_error, http_method_not_allowed,
+            http_multi_status, http_no_content, http_not_found, http_ok, prefers_minimal,
+        },
+        error::AppError,
+        middleware::{request_id::request_id_of, timeout::timeout},
     },
     users::{
-        dto::{CreateUserDtoRequest, UpdateUserDtoRequest, UserIdDtoResponse},
-        users_model::{USERS_COLLECTION, User},
-        users_service,
+        dto::{
+            BulkCreateDtoResponse, BulkCreateErrorDto, BulkDeleteUsersDtoRequest,
+            BulkUpdateDtoResponse, BulkUpdateUsersDtoRequest, CreateUserDtoRequest,
+            IndexDtoResponse, ScoredUserDtoResponse, SearchQuery, TextSearchQuery,
+            UpdateUserDtoRequest, UpdateUserDtoResponse, UserIdDtoResponse, UserProfileResponse,
+            UsersCountDtoResponse, UsersFilterQuery, Validate,
+        },
+        users_cache::UserCache,
+        users_export,
+        users_model::User,
+        users_repository,
+        users_service::{self, UserReads},
+        users_store::{Page, StoreScope, UserStore},
     },
 };
-use actix_web::{HttpResponse, delete, get, patch, post, web};
-use log::error;
-use mongodb::{
-    Client, Collection,
-    bson::{Bson, doc, oid::ObjectId, to_document},
-    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+use actix_web::{
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, delete, get,
+    guard::GuardContext,
+    http::{
+        StatusCode,
+        header::{
+            ContentDisposition, ContentEncoding, DispositionParam, DispositionType, ETag,
+            EntityTag, HeaderName, HeaderValue, IfNoneMatch,
+        },
+    },
+    middleware::from_fn,
+    patch, post, put, web,
+};
+use log::{error, warn};
+use mongodb::bson::{DateTime, oid::ObjectId, to_document};
+use redis::aio::ConnectionManager;
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
 };
 
+/// Results of `GET /users/search` and `GET /users/text-search` without `limit`, and the
+/// highest `limit` accepted.
+const SEARCH_DEFAULT_LIMIT: u64 = 20;
+const SEARCH_MAX_LIMIT: u64 = 100;
+
+/// Response header of `PATCH /users/{id}`, `true` when the update changed the document.
+const MODIFIED_HEADER: &str = "x-modified";
+
+/// Resolves the storage scope of the request.
+///
+/// The database comes from `Settings` (see `Settings::database_for`), the MongoDB
+/// operation comment is the request id when `MONGODB_QUERY_COMMENT` is on.
+fn store_scope(config: &Settings, req: &HttpRequest) -> StoreScope {
+    StoreScope {
+        database: config.database_for(req),
+        comment: users_repository::query_comment(config.mongodb_query_comment, request_id_of(req)),
+    }
+}
+
+/// Message of the 400 answered to a malformed user id, checked before any data access.
+const INVALID_ID_MESSAGE: &str = "Invalid user id format";
+
+/// 400 listing every failed check of `dto`, `None` when it is valid.
+fn validation_error(dto: &impl Validate) -> Option<HttpResponse> {
+    dto.validate()
+        .err()
+        .map(|errors| http_bad_request(format!("Invalid fields: {}", errors.join("; "))))
+}
+
+/// 400 answered to `fields`, `after`, `skip` and `limit` outside `GET /users`.
+fn list_only_param_rejected(query: &UsersFilterQuery) -> Option<HttpResponse> {
+    query.list_only_param().map(|name| {
+        http_bad_request(format!(
+            "Query parameter {} is only supported by GET /users",
+            name
+        ))
+    })
+}
+
+/// Response header carrying the `after` cursor of the next `GET /users` page.
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Sets the [`NEXT_CURSOR_HEADER`] of `res` when there is a next page.
+fn with_next_cursor(mut res: HttpResponse, cursor: Option<String>) -> HttpResponse {
+    if let Some(value) = cursor.and_then(|cursor| HeaderValue::from_str(&cursor).ok()) {
+        res.headers_mut()
+            .insert(HeaderName::from_static(NEXT_CURSOR_HEADER), value);
+    }
+    res
+}
+
+/// `GET /users` page of `query`, `Err` with the 400 message of invalid paging parameters.
+fn list_page(query: &UsersFilterQuery) -> Result<Page, String> {
+    let after = match &query.after {
+        Some(after) => Some(
+            ObjectId::parse_str(after.trim())
+                .map_err(|_| "Invalid after cursor, expected a user id".to_string())?,
+        ),
+        None => None,
+    };
+    if after.is_some() && query.skip.is_some() {
+        return Err("Query parameters after and skip can't be combined".into());
+    }
+    if query.limit == Some(0) {
+        return Err("Query parameter limit must be at least 1".into());
+    }
+    Ok(Page {
+        after,
+        skip: query.skip.unwrap_or(0),
+        limit: query.limit,
+    })
+}
+
+/// `user` as a JSON object restricted to `fields`, keys as serialized (`id`, not `_id`).
+fn select_fields(user: UserDtoResponse, fields: &[&str]) -> Result<Map<String, Value>, String> {
+    let Value::Object(mut user) = serde_json::to_value(user).map_err(|err| err.to_string())? else {
+        return Err("User is not serialized as a JSON object".into());
+    };
+    user.retain(|key, _| fields.contains(&key.as_str()));
+    Ok(user)
+}
+
+/// 401/403 when `query` asks for soft-deleted users without the admin token.
+async fn include_deleted_denied(
+    req: &HttpRequest,
+    query: &UsersFilterQuery,
+) -> Option<HttpResponse> {
+    if !query.include_deleted {
+        return None;
+    }
+    AdminAuth::extract(req)
+        .await
+        .err()
+        .map(|err| err.error_response())
+}
+
 /// REST API controller for user management.
 ///
 /// All routes are prefixed with `/users` as specified in main.rs via `web::scope("/users")`.
 ///
 /// # Routes
-/// - `GET /users` - Get all users
-/// - `GET /users/{id}` - Get user by ID
-/// - `POST /users` - Create new user
-/// - `PATCH /users/{id}` - Update user by ID
-/// - `DELETE /users/{id}` - Delete user by ID
-
-#[get("")]
-async fn get_all(client: web::Data<Client>) -> HttpResponse {
-    let collection: Collection<User> = client.database(DATABASE_NAME).collection(USERS_COLLECTION);
-
-    // Fetch from the network with batch size of 100 elements per network call
-    let find_opts: FindOptions = FindOptions::builder().batch_size(100).build();
-    let cursor = collection.find(doc! {}).with_options(find_opts).await;
-
-    let mut cursor = match cursor {
-        Ok(cursor) => cursor,
-        Err(e) => {
-            error!("Error running find: {}", e);
-            return http_internal_server_error("Database query error".into());
-        }
-    };
-
-    let mut users: Vec<UserDtoResponse> = Vec::new();
-    // Retrieve and deserialize user data from cursor
-    while cursor.advance().await.unwrap_or(false) {
-        let current = cursor.deserialize_current();
-        match current {
-            Ok(user) => {
-                let tmp = UserDtoResponse {
-                    id: user._id.to_hex(),
-                    first_name: user.first_name,
-                    last_name: user.last_name,
-                    email: user.email,
-                    age: user.age,
-                };
-                users.push(tmp)
+/// - `GET /users?<filter>` - Get the matching users (all without a filter) sorted by
+///   `LIST_DEFAULT_SORT` (204 when empty and `EMPTY_LIST_NO_CONTENT` or
+///   `Prefer: return=minimal` is set, 200 `[]` otherwise)
+/// - `GET /users?fields=id,email` - Same, each user restricted to the listed fields (400 on
+///   an unknown field), only those are read from MongoDB
+/// - `GET /users?limit=<n>&after=<id>` - Same, the page of users past the `after` id in `_id`
+///   order, `X-Next-Cursor` holds the `after` of the next one (`skip=<n>` pages by offset)
+/// - `GET /users/count?<filter>` - Count users, estimated when unfiltered and capped at
+///   `COUNT_EXACT_LIMIT` when filtered (`exact` tells which)
+/// - `GET /users?include_deleted=true`, `GET /users/count?include_deleted=true` - Same,
+///   soft-deleted users included (requires the admin token)
+/// - `GET /users/random` - Get a random user
+/// - `GET /users/search?q=<text>&skip=<n>&limit=<n>` - Users whose names or email contain
+///   the text, case-insensitively, sorted by `LIST_DEFAULT_SORT` (200 `[]` when none match)
+/// - `GET /users/text-search?q=<words>&limit=<n>` - Users whose names match the words,
+///   most relevant first with their `score` (MongoDB `$text`, needs the text index)
+/// - `GET /users/export.zip` - Download every user as a ZIP of `{id}.json` files, streamed
+///   while read (requires the admin token)
+/// - `GET /users/{id}` - Get user by ID (404 when it does not exist), cached in Redis when
+///   `USER_CACHE_TTL_SECS` is set
+/// - `GET /users/{id}/profile` - Get user by ID with computed fields (`full_name`,
+///   `age_group`, `account_age_days`)
+/// - `POST /users` - Create new user, 201 with its `Location`
+/// - `POST /users/bulk` - Create up to `MAX_BULK_SIZE` users, each entry independently,
+///   answers the created ids and the per-entry errors
+/// - `PUT /users/{id}` - Replace user by ID with a full body (404 when it does not exist),
+///   answers the stored user
+/// - `PATCH /users/{id}` - Update user by ID (404 when it does not exist, 400 on an empty
+///   body or an email held by another user), answers `{"user": ..., "changed": [...]}` with
+///   the fields that differed from the stored user, nothing is written when none did
+/// - `PATCH /users?<filter>` - `$set` the given fields on every matching user (requires the
+///   confirmation token), answers the matched/modified counts
+/// - `DELETE /users/{id}` - Soft-delete user by ID (sets `deleted_at`), 404 when it does
+///   not exist or is already deleted
+/// - `DELETE /users/{id}/permanent` - Remove user by ID for good, soft-deleted or not
+///   (requires the admin token)
+/// - `DELETE /users` - Soft-delete the listed users (requires the confirmation token),
+///   answers 207 Multi-Status with one result per id
+///
+/// Soft-deleted users answer 404 and are left out of every list, search and update.
+///
+/// Create and update bodies are validated before any data access (see `dto::Validate`),
+/// a 400 lists every invalid field. Any other method on these paths answers 405 with an
+/// `Allow` header listing the supported ones.
+
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    params(UsersFilterQuery),
+    responses(
+        (status = 200, description = "Matching users sorted by LIST_DEFAULT_SORT, or by id with `after`, only the `fields` ones when set", body = [UserDtoResponse],
+            headers(("X-Next-Cursor" = String, description = "`after` value of the next page, on full pages in id order"))),
+        (status = 204, description = "No match, with EMPTY_LIST_NO_CONTENT or Prefer: return=minimal"),
+        (status = 400, description = "Unknown or malformed filter parameter, unknown field in `fields`, malformed `after` cursor, `after` with `skip` or `limit=0`"),
+        (status = 401, description = "Soft-deleted users requested without the admin token", body = ErrorResponse),
+        (status = 403, description = "Soft-deleted users requested with a wrong admin token", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[get("", wrap = "from_fn(timeout)")]
+async fn get_all(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    query: web::Query<UsersFilterQuery>,
+) -> HttpResponse {
+    let query = users_service::normalize_filter(query.into_inner());
+    let fields = match query.fields() {
+        Ok(fields) => fields,
+        Err(err) => return http_bad_request(err),
+    };
+    let page = match list_page(&query) {
+        Ok(page) => page,
+        Err(err) => return http_bad_request(err),
+    };
+    if let Some(res) = include_deleted_denied(&req, &query).await {
+        return res;
+    }
+    let scope = store_scope(&config, &req);
+    let sort = &config.list_default_sort;
+    let users: Vec<UserDtoResponse> =
+        match users_service::list(store.get_ref(), &scope, &query, sort, page).await {
+            Ok(users) => users.into_iter().map(UserDtoResponse::from).collect(),
+            Err(err) => {
+                error!("{}", err);
+                return http_internal_server_error("Database query error".into());
             }
-            Err(err) => error!("Not valid user; {}", err),
+        };
+
+    // Empty results answer 204 when enabled by config or requested via `Prefer: return=minimal`
+    if users.is_empty() && (config.empty_list_no_content || prefers_minimal(&req)) {
+        return http_no_content();
+    }
+    // A full page in `_id` order can be continued from its last id
+    let id_order =
+        page.after.is_some() || (sort.field == "_id" && !sort.descending && page.skip == 0);
+    let next_cursor = users
+        .last()
+        .filter(|_| id_order && page.limit == Some(users.len() as u64))
+        .map(|user| user.id.clone());
+
+    let Some(fields) = fields else {
+        return with_next_cursor(http_ok(users), next_cursor);
+    };
+    match users
+        .into_iter()
+        .map(|user| select_fields(user, &fields))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(users) => with_next_cursor(http_ok(users), next_cursor),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Database query error".into())
         }
     }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/count",
+    tag = "users",
+    params(UsersFilterQuery),
+    responses(
+        (status = 200, description = "Number of matching users", body = UsersCountDtoResponse),
+        (status = 400, description = "Unknown or malformed filter parameter"),
+        (status = 401, description = "Soft-deleted users requested without the admin token", body = ErrorResponse),
+        (status = 403, description = "Soft-deleted users requested with a wrong admin token", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[get("count", wrap = "from_fn(timeout)")]
+async fn count(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    query: web::Query<UsersFilterQuery>,
+) -> HttpResponse {
+    if let Some(res) = list_only_param_rejected(&query) {
+        return res;
+    }
+    let query = users_service::normalize_filter(query.into_inner());
+    if let Some(res) = include_deleted_denied(&req, &query).await {
+        return res;
+    }
+    let limit = (config.count_exact_limit > 0).then_some(config.count_exact_limit);
+
+    let scope = store_scope(&config, &req);
+    match users_service::count(store.get_ref(), &scope, &query, limit).await {
+        Ok((count, exact)) => http_ok(UsersCountDtoResponse { count, exact }),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Count failed".into())
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/random",
+    tag = "users",
+    responses(
+        (status = 200, description = "A random user", body = UserDtoResponse),
+        (status = 404, description = "No users", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[get("random", wrap = "from_fn(timeout)")]
+async fn get_random(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+) -> HttpResponse {
+    let scope = store_scope(&config, &req);
+
+    match users_service::find_random(store.get_ref(), &scope).await {
+        Ok(Some(user)) => http_ok(UserDtoResponse::from(user)),
+        Ok(None) => http_not_found("No users found".into()),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Random user lookup failed".into())
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/search",
+    tag = "users",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Users containing the text, empty when none match", body = [UserDtoResponse]),
+        (status = 400, description = "Empty or malformed query", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[get("search", wrap = "from_fn(timeout)")]
+async fn search(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    query: web::Query<SearchQuery>,
+) -> HttpResponse {
+    let term = query.q.trim();
+    if term.is_empty() {
+        return http_bad_request("Query parameter q must not be empty".into());
+    }
+    let page = Page {
+        skip: query.skip.unwrap_or(0),
+        limit: Some(
+            query
+                .limit
+                .unwrap_or(SEARCH_DEFAULT_LIMIT)
+                .clamp(1, SEARCH_MAX_LIMIT),
+        ),
+        ..Default::default()
+    };
+
+    let scope = store_scope(&config, &req);
+    match users_service::search(
+        store.get_ref(),
+        &scope,
+        term,
+        &config.list_default_sort,
+        page,
+    )
+    .await
+    {
+        Ok(users) => http_ok(
+            users
+                .into_iter()
+                .map(UserDtoResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Search failed".into())
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/text-search",
+    tag = "users",
+    params(TextSearchQuery),
+    responses(
+        (status = 200, description = "Users matching the words, most relevant first", body = [ScoredUserDtoResponse]),
+        (status = 400, description = "Empty or malformed query", body = ErrorResponse),
+        (status = 503, description = "The users text index does not exist (TEXT_INDEX_MISSING)", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[get("text-search", wrap = "from_fn(timeout)")]
+async fn text_search(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    query: web::Query<TextSearchQuery>,
+) -> HttpResponse {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return http_bad_request("Query parameter q must not be empty".into());
+    }
+    let limit = query
+        .limit
+        .unwrap_or(SEARCH_DEFAULT_LIMIT)
+        .clamp(1, SEARCH_MAX_LIMIT);
 
-    // Alternative approach using futures (commented out)
-    // while let Some(result) = cursor.try_next().await.unwrap_or_else(|err| {
-    //     error!("Not valid user: {}", err);
-    //     None
-    // }) {
-    //     users.push(result);
-    // }
+    let scope = store_scope(&config, &req);
+    match users_service::text_search(store.get_ref(), &scope, q, limit).await {
+        Ok(Some(users)) => http_ok(
+            users
+                .into_iter()
+                .map(ScoredUserDtoResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Ok(None) => http_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::TextIndexMissing,
+            "Text search unavailable: the users text index does not exist, restart the service or run POST /admin/reindex".into(),
+        ),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Text search failed".into())
+        }
+    }
+}
 
-    http_ok(users)
+#[utoipa::path(
+    get,
+    path = "/users/_indexes",
+    tag = "users",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Indexes of the users collection, empty on the memory backend", body = Vec<IndexDtoResponse>),
+        (status = 401, description = "Missing admin token", body = ErrorResponse),
+        (status = 403, description = "Wrong admin token", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[get("_indexes", wrap = "from_fn(timeout)")]
+async fn list_indexes(
+    _admin: AdminAuth,
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+) -> HttpResponse {
+    let scope = store_scope(&config, &req);
+    match users_service::list_indexes(store.get_ref(), &scope).await {
+        Ok(indexes) => http_ok(
+            indexes
+                .into_iter()
+                .map(IndexDtoResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error("Failed to list the users indexes".into())
+        }
+    }
 }
 
-#[get("{id}")]
-async fn get_by_id(client: web::Data<Client>, id: web::Path<String>) -> HttpResponse {
+#[utoipa::path(
+    get,
+    path = "/users/export.zip",
+    tag = "users",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "ZIP archive of one {id}.json file per user", content_type = "application/zip", body = Vec<u8>),
+        (status = 401, description = "Missing admin token", body = ErrorResponse),
+        (status = 403, description = "Wrong admin token", body = ErrorResponse),
+    )
+)]
+#[get("export.zip", wrap = "from_fn(timeout)")]
+async fn export_zip(
+    _admin: AdminAuth,
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+) -> HttpResponse {
+    let scope = store_scope(&config, &req);
+    let users = users_service::stream_all(store.get_ref(), &scope);
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        // already deflated, skips the `Compress` middleware
+        .insert_header(ContentEncoding::Identity)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(
+                users_export::EXPORT_FILE_NAME.into(),
+            )],
+        })
+        .streaming(users_export::zip_stream(users))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, description = "User id, 24 hex characters")),
+    responses(
+        (status = 200, description = "The user, with its weak `ETag`", body = UserDtoResponse),
+        (status = 304, description = "`If-None-Match` matches the current `ETag`, no body"),
+        (status = 400, description = "Malformed id", body = ErrorResponse),
+        (status = 404, description = "User not found (USER_NOT_FOUND)", body = ErrorResponse),
+        (status = 500, description = "Database or cache error", body = ErrorResponse),
+    )
+)]
+#[get("{id}", guard = "not_literal_path", wrap = "from_fn(timeout)")]
+async fn get_by_id(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    reads: web::Data<UserReads>,
+    redis: Option<web::Data<ConnectionManager>>,
+    id: web::Path<String>,
+) -> HttpResponse {
     let id = id.into_inner();
-    let object_id = ObjectId::parse_str(&id).unwrap_or_default();
-    let collection: Collection<User> = client.database(DATABASE_NAME).collection(USERS_COLLECTION);
-
-    match collection.find_one(doc! { "_id": object_id }).await {
-        Ok(Some(user)) => http_ok(UserDtoResponse {
-            id: user._id.to_hex(),
-            first_name: user.first_name,
-            last_name: user.last_name,
-            email: user.email,
-            age: user.age,
-        }),
-        Ok(None) => http_bad_request(format!("User not found for id {}", id)),
+    let Ok(object_id) = ObjectId::parse_str(&id) else {
+        return http_bad_request(INVALID_ID_MESSAGE.into());
+    };
+    let scope = store_scope(&config, &req);
+    let cache = UserCache::new(redis.as_ref().map(|redis| redis.get_ref()), &config, &scope);
+    if let Some(cache) = &cache {
+        match cache.get(object_id).await {
+            Ok(Some(user)) => return http_ok_conditional(&req, user),
+            Ok(None) => {}
+            Err(err) => {
+                error!("{}", err);
+                return http_internal_server_error(format!("Generic error finding id {}", id));
+            }
+        }
+    }
+    // Concurrent lookups of the same id share one query when coalescing is enabled
+    let reads = config.coalesce_reads.then_some(reads.get_ref());
+
+    match users_service::find_by_id(&store, &scope, object_id, reads).await {
+        Ok(Some(user)) => {
+            let user = UserDtoResponse::from(user);
+            if let Some(cache) = &cache
+                && let Err(err) = cache.set(object_id, &user).await
+            {
+                error!("{}", err);
+                return http_internal_server_error(format!("Generic error finding id {}", id));
+            }
+            http_ok_conditional(&req, user)
+        }
+        Ok(None) => http_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::UserNotFound,
+            format!("User not found for id {}", id),
+        ),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error(format!("Generic error finding id {}", id))
+        }
+    }
+}
+
+/// Weak `ETag` of a user: a hash of its JSON representation and `updated_at`.
+///
+/// `DefaultHasher` uses fixed keys, instances running the same build agree on the tag.
+pub fn etag_for(user: &UserDtoResponse) -> EntityTag {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(user)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    user.updated_at.timestamp_millis().hash(&mut hasher);
+    EntityTag::new_weak(format!("{:016x}", hasher.finish()))
+}
+
+/// 200 with the user and its `ETag`, or an empty 304 when `If-None-Match` matches the tag.
+fn http_ok_conditional(req: &HttpRequest, user: UserDtoResponse) -> HttpResponse {
+    let etag = etag_for(&user);
+    let not_modified = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if not_modified {
+        return HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .finish();
+    }
+    HttpResponse::Ok().insert_header(ETag(etag)).json(user)
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}/profile",
+    tag = "users",
+    params(("id" = String, Path, description = "User id, 24 hex characters")),
+    responses(
+        (status = 200, description = "The user with its computed fields", body = UserProfileResponse),
+        (status = 400, description = "Malformed id", body = ErrorResponse),
+        (status = 404, description = "User not found (USER_NOT_FOUND)", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[get("{id}/profile", wrap = "from_fn(timeout)")]
+async fn get_profile(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let Ok(object_id) = ObjectId::parse_str(&id) else {
+        return http_bad_request(INVALID_ID_MESSAGE.into());
+    };
+    let scope = store_scope(&config, &req);
+
+    match users_service::find_profile(store.get_ref(), &scope, object_id).await {
+        Ok(Some(profile)) => http_ok(UserProfileResponse::from(profile)),
+        Ok(None) => http_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::UserNotFound,
+            format!("User not found for id {}", id),
+        ),
         Err(err) => {
             error!("{}", err);
             http_internal_server_error(format!("Generic error finding id {}", id))
@@ -97,103 +642,649 @@ async fn get_by_id(client: web::Data<Client>, id: web::Path<String>) -> HttpResp
     }
 }
 
-#[post("")]
-async fn create(client: web::Data<Client>, dto: web::Json<CreateUserDtoRequest>) -> HttpResponse {
-    let collection: Collection<User> = client.database(DATABASE_NAME).collection(USERS_COLLECTION);
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body = CreateUserDtoRequest,
+    responses(
+        (status = 201, description = "User created", body = UserIdDtoResponse,
+            headers(("Location" = String, description = "Path of the created user"))),
+        (status = 400, description = "Invalid fields (VALIDATION_FAILED) or email taken (EMAIL_EXISTS)", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[post("", wrap = "from_fn(timeout)")]
+async fn create(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    dto: web::Json<CreateUserDtoRequest>,
+) -> HttpResponse {
+    if let Some(res) = validation_error(&*dto) {
+        return res;
+    }
+    let scope = store_scope(&config, &req);
+
+    let email = users_service::normalize_email(&dto.email);
+    let email_normalized = config.email_normalization_report && email != dto.email;
+    if email_normalized {
+        warn!(
+            "Email {:?} normalized to {:?} before storing",
+            dto.email, email
+        );
+    }
 
+    let now = DateTime::now();
     let user = User {
         _id: ObjectId::new(),
         first_name: dto.first_name.clone(),
         last_name: dto.last_name.clone(),
-        email: dto.email.clone(),
+        email: email.clone(),
         age: dto.age,
+        created_at: Some(now),
+        updated_at: Some(now),
+        deleted_at: None,
     };
 
-    let can_continue = match collection.find_one(doc! { "email": &dto.email }).await {
-        Ok(Some(_)) => false,
-        Ok(None) => true,
+    // with MONGODB_TRANSACTIONS the insert checks the email itself, in its transaction
+    if !config.mongodb_transactions {
+        match users_service::find_by_email(store.get_ref(), &scope, &email).await {
+            Ok(Some(_)) => {
+                return http_error(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::EmailExists,
+                    "Already exists".into(),
+                );
+            }
+            Ok(None) => {}
+            // an outage must not tell the client the email is taken
+            Err(err) => {
+                error!("{}", err);
+                return http_internal_server_error("Failed to insert user".into());
+            }
+        }
+    }
+
+    match users_service::insert(store.get_ref(), &scope, user).await {
+        Ok(oid) => http_created(
+            &format!("{}/{}", req.path().trim_end_matches('/'), oid.to_hex()),
+            UserIdDtoResponse {
+                id: oid.to_hex(),
+                email: email_normalized.then_some(email),
+            },
+        ),
+        // taken, or taken by a concurrent creation after the check above
+        Err(AppError::Duplicate(message)) => {
+            warn!("{}", message);
+            http_error(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::EmailExists,
+                "Already exists".into(),
+            )
+        }
         Err(err) => {
             error!("{}", err);
-            false
+            http_internal_server_error("Failed to insert user".into())
         }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/bulk",
+    tag = "users",
+    request_body = Vec<CreateUserDtoRequest>,
+    responses(
+        (status = 200, description = "Created ids and per-entry errors", body = BulkCreateDtoResponse),
+        (status = 400, description = "Empty body or more than MAX_BULK_SIZE entries", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[post("bulk", wrap = "from_fn(timeout)")]
+async fn create_bulk(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    dtos: web::Json<Vec<CreateUserDtoRequest>>,
+) -> HttpResponse {
+    let dtos = dtos.into_inner();
+    if dtos.is_empty() {
+        return http_bad_request("No users to create".into());
+    }
+    if dtos.len() > config.max_bulk_size {
+        return http_bad_request(format!(
+            "At most {} users per request",
+            config.max_bulk_size
+        ));
+    }
+
+    let mut errors = Vec::new();
+    let mut emails = HashSet::with_capacity(dtos.len());
+    // request index of each user sent to the store
+    let mut indexes = Vec::with_capacity(dtos.len());
+    let mut users = Vec::with_capacity(dtos.len());
+    let now = DateTime::now();
+    for (index, dto) in dtos.into_iter().enumerate() {
+        if let Err(invalid) = dto.validate() {
+            errors.push(BulkCreateErrorDto {
+                index,
+                message: format!("Invalid fields: {}", invalid.join("; ")),
+            });
+            continue;
+        }
+        let email = users_service::normalize_email(&dto.email);
+        if !emails.insert(email.clone()) {
+            errors.push(BulkCreateErrorDto {
+                index,
+                message: "Duplicate email in request".into(),
+            });
+            continue;
+        }
+        indexes.push(index);
+        users.push(User {
+            _id: ObjectId::new(),
+            first_name: dto.first_name,
+            last_name: dto.last_name,
+            email,
+            age: dto.age,
+            created_at: Some(now),
+            updated_at: Some(now),
+            deleted_at: None,
+        });
+    }
+
+    // emails already stored are rejected by the unique email index, entry by entry
+    let ids: Vec<ObjectId> = users.iter().map(|user| user._id).collect();
+    let scope = store_scope(&config, &req);
+    let failed: HashMap<usize, &str> =
+        match users_service::insert_many(store.get_ref(), &scope, users).await {
+            Ok(failed) => failed
+                .into_iter()
+                .map(|failure| (failure.index, failure.message()))
+                .collect(),
+            Err(err) => {
+                error!("{}", err);
+                return http_internal_server_error("Bulk create failed".into());
+            }
+        };
+
+    let mut inserted = Vec::with_capacity(ids.len());
+    for (position, id) in ids.into_iter().enumerate() {
+        match failed.get(&position) {
+            Some(message) => errors.push(BulkCreateErrorDto {
+                index: indexes[position],
+                message: (*message).to_owned(),
+            }),
+            None => inserted.push(id.to_hex()),
+        }
+    }
+    errors.sort_by_key(|error| error.index);
+
+    http_ok(BulkCreateDtoResponse { inserted, errors })
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, description = "User id, 24 hex characters")),
+    request_body = CreateUserDtoRequest,
+    responses(
+        (status = 200, description = "The stored user", body = UserDtoResponse),
+        (status = 400, description = "Malformed id, invalid fields or email taken (EMAIL_EXISTS)", body = ErrorResponse),
+        (status = 404, description = "User not found (USER_NOT_FOUND)", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[put("{id}", guard = "not_literal_path", wrap = "from_fn(timeout)")]
+async fn replace_by_id(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    redis: Option<web::Data<ConnectionManager>>,
+    id: web::Path<String>,
+    dto: web::Json<CreateUserDtoRequest>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let Ok(object_id) = ObjectId::parse_str(&id) else {
+        return http_bad_request(INVALID_ID_MESSAGE.into());
     };
-    if !can_continue {
-        return http_bad_request("Already exists".into());
+    if let Some(res) = validation_error(&*dto) {
+        return res;
+    }
+    let scope = store_scope(&config, &req);
+
+    let dto = dto.into_inner();
+    let email = users_service::normalize_email(&dto.email);
+    if config.email_normalization_report && email != dto.email {
+        warn!(
+            "Email {:?} normalized to {:?} before storing",
+            dto.email, email
+        );
     }
 
-    let insert_result = match collection.insert_one(user).await {
-        Ok(res) => res,
-        Err(e) => {
-            error!("Error inserting user: {}", e);
-            return http_internal_server_error("Failed to insert user".into());
+    // the email may only belong to the replaced user
+    match users_service::find_by_email(store.get_ref(), &scope, &email).await {
+        Ok(Some(other)) if other._id != object_id => {
+            return http_error(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::EmailExists,
+                "Already exists".into(),
+            );
         }
+        Ok(_) => {}
+        Err(err) => {
+            error!("{}", err);
+            return http_internal_server_error(format!("Generic error finding id {}", id));
+        }
+    }
+
+    // the store keeps the stored `created_at`
+    let user = User {
+        _id: object_id,
+        first_name: dto.first_name,
+        last_name: dto.last_name,
+        email,
+        age: dto.age,
+        created_at: None,
+        updated_at: Some(DateTime::now()),
+        deleted_at: None,
     };
-    // Extract and return the inserted ObjectId
-    match insert_result.inserted_id {
-        Bson::ObjectId(oid) => http_ok(UserIdDtoResponse { id: oid.to_hex() }),
-        _ => http_internal_server_error("Failed to insert user".into()),
+    match users_service::replace_by_id(store.get_ref(), &scope, user).await {
+        Ok(Some(user)) => {
+            if let Some(cache) =
+                UserCache::new(redis.as_ref().map(|redis| redis.get_ref()), &config, &scope)
+            {
+                cache.invalidate(object_id).await;
+            }
+            http_ok(UserDtoResponse::from(user))
+        }
+        Ok(None) => http_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::UserNotFound,
+            format!("User not found for id {}", id),
+        ),
+        // a concurrent write took the email after the check above
+        Err(AppError::Duplicate(message)) => {
+            warn!("{}", message);
+            http_error(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::EmailExists,
+                "Already exists".into(),
+            )
+        }
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error(format!("Generic error replacing id {}", id))
+        }
     }
 }
 
-#[patch("{id}")]
+#[utoipa::path(
+    patch,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, description = "User id, 24 hex characters")),
+    request_body = UpdateUserDtoRequest,
+    responses(
+        (status = 200, description = "The updated user and the fields that changed", body = UpdateUserDtoResponse,
+            headers(("X-Modified" = bool, description = "false when the update changed nothing"))),
+        (status = 400, description = "Malformed id, empty body, invalid fields or email taken (EMAIL_EXISTS)", body = ErrorResponse),
+        (status = 404, description = "User not found (USER_NOT_FOUND)", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[patch("{id}", guard = "not_literal_path", wrap = "from_fn(timeout)")]
 async fn update_by_id(
-    client: web::Data<Client>,
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    redis: Option<web::Data<ConnectionManager>>,
     id: web::Path<String>,
     dto: web::Json<UpdateUserDtoRequest>,
 ) -> HttpResponse {
     let id: String = id.into_inner(); // Extract ID from path parameter
-    let object_id = ObjectId::parse_str(&id).unwrap_or_default();
-    let collection: Collection<User> = client.database(DATABASE_NAME).collection(USERS_COLLECTION);
+    let Ok(object_id) = ObjectId::parse_str(&id) else {
+        return http_bad_request(INVALID_ID_MESSAGE.into());
+    };
+    if dto.is_empty() {
+        return http_bad_request("No fields to update".into());
+    }
+    if let Some(res) = validation_error(&*dto) {
+        return res;
+    }
+    let scope = store_scope(&config, &req);
 
-    let update_doc = to_document(&dto);
-    if update_doc.is_err() {
+    let mut dto = dto.into_inner();
+    dto.trim_names();
+    if let Some(submitted) = dto.email.take() {
+        let email = users_service::normalize_email(&submitted);
+        if config.email_normalization_report && email != submitted {
+            warn!(
+                "Email {:?} normalized to {:?} before storing",
+                submitted, email
+            );
+        }
+        dto.email = Some(email);
+    }
+
+    let Ok(set) = to_document(&dto) else {
         return http_bad_request("Invalid parameters".into());
+    };
+    if let Err(err) = users_service::check_update_fields(&set) {
+        return http_bad_request(err);
     }
 
-    let opts = FindOneAndUpdateOptions::builder()
-        .upsert(false)
-        .return_document(Some(ReturnDocument::After))
-        .build();
+    // One write, the user as it was before tells which fields changed. An email held by
+    // another user is refused by the unique email index.
+    let now = DateTime::now();
+    let before =
+        match users_service::update_by_id(store.get_ref(), &scope, object_id, set, now).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                return http_error(
+                    StatusCode::NOT_FOUND,
+                    ErrorCode::UserNotFound,
+                    format!("User not found for id {}", id),
+                );
+            }
+            Err(AppError::Duplicate(message)) => {
+                warn!("{}", message);
+                return http_error(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::EmailExists,
+                    "Already exists".into(),
+                );
+            }
+            Err(err) => {
+                error!("{}", err);
+                return http_internal_server_error(format!("Generic error updating id {}", id));
+            }
+        };
 
-    match collection
-        .find_one_and_update(
-            doc! {
-                "_id": object_id
-            },
-            doc! {
-                "$set": update_doc.unwrap()
-            },
-        )
-        .with_options(opts)
-        .await
-    {
-        Ok(Some(user)) => http_ok(UserDtoResponse {
-            id: user._id.to_hex(),
-            first_name: user.first_name,
-            last_name: user.last_name,
-            email: user.email,
-            age: user.age,
+    let changed = dto.changed_fields(&before);
+    let mut user = before;
+    dto.apply(&mut user);
+    if !changed.is_empty() {
+        user.updated_at = Some(now);
+        if let Some(cache) =
+            UserCache::new(redis.as_ref().map(|redis| redis.get_ref()), &config, &scope)
+        {
+            cache.invalidate(object_id).await;
+        }
+    }
+    updated_user_response(user, changed)
+}
+
+/// 200 answered by `PATCH /users/{id}`, `X-Modified` is `false` when `changed` is empty.
+fn updated_user_response(user: User, changed: Vec<String>) -> HttpResponse {
+    let modified = !changed.is_empty();
+    let mut response = http_ok(UpdateUserDtoResponse {
+        user: UserDtoResponse::from(user),
+        changed,
+    });
+    // Tells a real change from a matched-but-identical (no-op) update
+    response.headers_mut().insert(
+        HeaderName::from_static(MODIFIED_HEADER),
+        HeaderValue::from_static(if modified { "true" } else { "false" }),
+    );
+    response
+}
+
+#[utoipa::path(
+    patch,
+    path = "/users",
+    tag = "users",
+    params(UsersFilterQuery),
+    request_body = BulkUpdateUsersDtoRequest,
+    responses(
+        (status = 200, description = "Matched and modified counts", body = BulkUpdateDtoResponse),
+        (status = 400, description = "Missing confirmation (CONFIRMATION_REQUIRED) or invalid fields", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[patch("", wrap = "from_fn(timeout)")]
+async fn bulk_update(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    query: web::Query<UsersFilterQuery>,
+    dto: web::Json<BulkUpdateUsersDtoRequest>,
+) -> HttpResponse {
+    let mut dto = dto.into_inner();
+    // An empty filter matches the whole collection, the confirmation is always required
+    if !config.is_delete_confirmed(dto.confirm.as_deref()) {
+        return http_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ConfirmationRequired,
+            "Missing or invalid confirmation token".into(),
+        );
+    }
+    if query.include_deleted {
+        return http_bad_request("Soft-deleted users cannot be updated".into());
+    }
+    if let Some(res) = list_only_param_rejected(&query) {
+        return res;
+    }
+    // Emails are unique, a bulk update could only ever apply to a single user
+    if dto.set.email.is_some() {
+        return http_bad_request("Field email cannot be bulk updated".into());
+    }
+    if let Some(res) = validation_error(&dto.set) {
+        return res;
+    }
+    dto.set.trim_names();
+
+    let set = match to_document(&dto.set) {
+        Ok(set) => set,
+        Err(_) => return http_bad_request("Invalid parameters".into()),
+    };
+    if set.is_empty() {
+        return http_bad_request("No fields to update".into());
+    }
+    if let Err(err) = users_service::check_update_fields(&set) {
+        return http_bad_request(err);
+    }
+
+    let query = users_service::normalize_filter(query.into_inner());
+
+    let scope = store_scope(&config, &req);
+    match users_service::update_many(store.get_ref(), &scope, &query, set).await {
+        Ok(res) => http_ok(BulkUpdateDtoResponse {
+            matched: res.matched,
+            modified: res.modified,
         }),
-        Ok(None) => http_bad_request(format!("Generic error finding id {}", id)),
         Err(err) => {
             error!("{}", err);
-            http_internal_server_error(format!("Generic error finding id {}", id))
+            http_internal_server_error("Bulk update failed".into())
         }
     }
 }
 
-#[delete("{id}")]
-async fn delete_by_id(client: web::Data<Client>, id: web::Path<String>) -> HttpResponse {
-    let id = id.into_inner();
-    let res = users_service::delete_by_id(client, &id).await;
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, description = "User id, 24 hex characters")),
+    responses(
+        (status = 204, description = "User soft-deleted"),
+        (status = 400, description = "Malformed id", body = ErrorResponse),
+        (status = 404, description = "User not found or already deleted (USER_NOT_FOUND)", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[delete("{id}", guard = "not_literal_path", wrap = "from_fn(timeout)")]
+async fn delete_by_id(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    redis: Option<web::Data<ConnectionManager>>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.into_inner())
+        .map_err(|_| AppError::Validation(INVALID_ID_MESSAGE.into()))?;
+    let scope = store_scope(&config, &req);
+    users_service::delete_by_id(store.get_ref(), &scope, object_id).await?;
+    if let Some(cache) =
+        UserCache::new(redis.as_ref().map(|redis| redis.get_ref()), &config, &scope)
+    {
+        cache.invalidate(object_id).await;
+    }
+    Ok(http_no_content())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{id}/permanent",
+    tag = "users",
+    params(("id" = String, Path, description = "User id, 24 hex characters")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 204, description = "User removed"),
+        (status = 400, description = "Malformed id", body = ErrorResponse),
+        (status = 401, description = "Missing admin token", body = ErrorResponse),
+        (status = 403, description = "Wrong admin token", body = ErrorResponse),
+        (status = 404, description = "User not found (USER_NOT_FOUND)", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[delete("{id}/permanent", wrap = "from_fn(timeout)")]
+async fn hard_delete_by_id(
+    _admin: AdminAuth,
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    redis: Option<web::Data<ConnectionManager>>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let object_id = ObjectId::parse_str(id.into_inner())
+        .map_err(|_| AppError::Validation(INVALID_ID_MESSAGE.into()))?;
+    let scope = store_scope(&config, &req);
+    users_service::hard_delete_by_id(store.get_ref(), &scope, object_id).await?;
+    if let Some(cache) =
+        UserCache::new(redis.as_ref().map(|redis| redis.get_ref()), &config, &scope)
+    {
+        cache.invalidate(object_id).await;
+    }
+    Ok(http_no_content())
+}
 
-    match res {
-        Ok(_) => http_no_content(),
+#[utoipa::path(
+    delete,
+    path = "/users",
+    tag = "users",
+    request_body = BulkDeleteUsersDtoRequest,
+    responses(
+        (status = 207, description = "One result per requested id, in request order", body = MultiStatusResponse),
+        (status = 400, description = "Missing confirmation (CONFIRMATION_REQUIRED) or no ids", body = ErrorResponse),
+        (status = 500, description = "Database error", body = ErrorResponse),
+    )
+)]
+#[delete("", wrap = "from_fn(timeout)")]
+async fn bulk_delete(
+    req: HttpRequest,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    redis: Option<web::Data<ConnectionManager>>,
+    dto: web::Json<BulkDeleteUsersDtoRequest>,
+) -> HttpResponse {
+    let dto = dto.into_inner();
+    if !config.is_delete_confirmed(dto.confirm.as_deref()) {
+        return http_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ConfirmationRequired,
+            "Missing or invalid confirmation token".into(),
+        );
+    }
+    // An empty id list must never turn into an empty (match everything) filter
+    if dto.ids.is_empty() {
+        return http_bad_request("No ids to delete".into());
+    }
+
+    let mut results: Vec<MultiStatusItem> = Vec::with_capacity(dto.ids.len());
+    let mut ids = Vec::with_capacity(dto.ids.len());
+    for id in &dto.ids {
+        if let Ok(oid) = ObjectId::parse_str(id) {
+            ids.push(oid);
+        }
+    }
+
+    let scope = store_scope(&config, &req);
+    let existing = match users_service::find_existing_ids(store.get_ref(), &scope, &ids).await {
+        Ok(existing) => existing,
         Err(err) => {
             error!("{}", err);
-            http_internal_server_error(format!("Delete failed for id {}", id))
+            return http_internal_server_error("Bulk delete failed".into());
+        }
+    };
+    if !existing.is_empty()
+        && let Err(err) =
+            users_service::delete_many_by_ids(store.get_ref(), &scope, &existing).await
+    {
+        error!("{}", err);
+        return http_internal_server_error("Bulk delete failed".into());
+    }
+    if let Some(cache) =
+        UserCache::new(redis.as_ref().map(|redis| redis.get_ref()), &config, &scope)
+    {
+        for id in &existing {
+            cache.invalidate(*id).await;
         }
     }
+
+    // One result per requested id, in request order
+    for id in dto.ids {
+        let item = match ObjectId::parse_str(&id) {
+            Err(_) => MultiStatusItem {
+                id,
+                status: StatusCode::BAD_REQUEST.as_u16(),
+                message: Some("Invalid user id".into()),
+            },
+            Ok(oid) if existing.contains(&oid) => MultiStatusItem {
+                id,
+                status: StatusCode::NO_CONTENT.as_u16(),
+                message: None,
+            },
+            Ok(_) => MultiStatusItem {
+                id,
+                status: StatusCode::NOT_FOUND.as_u16(),
+                message: Some("User not found".into()),
+            },
+        };
+        results.push(item);
+    }
+
+    http_multi_status(results)
+}
+
+/// Methods supported by each path, answered in `Allow` by the 405 fallbacks of `config`.
+///
+/// Entries without `{` are the literal paths, see [`not_literal_path`].
+const ALLOWED_METHODS: [(&str, &[&str]); 11] = [
+    ("", &["GET", "POST", "PATCH", "DELETE"]),
+    ("count", &["GET"]),
+    ("random", &["GET"]),
+    ("search", &["GET"]),
+    ("text-search", &["GET"]),
+    ("export.zip", &["GET"]),
+    ("_indexes", &["GET"]),
+    ("bulk", &["POST"]),
+    ("{id}", &["GET", "PUT", "PATCH", "DELETE"]),
+    ("{id}/profile", &["GET"]),
+    ("{id}/permanent", &["DELETE"]),
+];
+
+/// Guard of the `{id}` routes: the last path segment is not a literal path (`count`,
+/// `bulk`, ...).
+///
+/// Literal paths are registered first, this also keeps a method they don't support from
+/// being taken for a user lookup: `GET /users/bulk` answers 405, not 400 for a malformed id.
+fn not_literal_path(ctx: &GuardContext) -> bool {
+    let segment = ctx.head().uri.path().rsplit('/').next().unwrap_or_default();
+    !ALLOWED_METHODS
+        .iter()
+        .any(|(path, _)| !path.is_empty() && !path.contains('{') && *path == segment)
 }
 
 /// Service configuration for user routes.
@@ -201,8 +1292,841 @@ async fn delete_by_id(client: web::Data<Client>, id: web::Path<String>) -> HttpR
 /// Registers all user endpoint handlers with the Actix-web application.
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_all);
+    // literal paths must be registered before `{id}`
+    cfg.service(count);
+    cfg.service(get_random);
+    cfg.service(search);
+    cfg.service(text_search);
+    cfg.service(export_zip);
+    cfg.service(list_indexes);
     cfg.service(get_by_id);
+    cfg.service(get_profile);
     cfg.service(create);
+    cfg.service(create_bulk);
+    cfg.service(replace_by_id);
     cfg.service(update_by_id);
+    cfg.service(bulk_update);
     cfg.service(delete_by_id);
+    cfg.service(hard_delete_by_id);
+    cfg.service(bulk_delete);
+    // registered last: a path reaches its fallback only when no handler accepts the method
+    for (path, allow) in ALLOWED_METHODS {
+        cfg.service(web::resource(path).to(move || async move { http_method_not_allowed(allow) }));
+    }
+}
+
+/// OpenAPI document of the users API, served on `/api-docs/openapi.json` (see `api_docs`).
+///
+/// Every handler above is listed in `paths`, and every body it reads or answers in `schemas`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "rust-web-starter users API",
+        description = "Errors answer the `ErrorResponse` envelope, clients branch on its `code`."
+    ),
+    paths(
+        get_all,
+        count,
+        get_random,
+        search,
+        text_search,
+        export_zip,
+        list_indexes,
+        get_by_id,
+        get_profile,
+        create,
+        create_bulk,
+        replace_by_id,
+        update_by_id,
+        bulk_update,
+        delete_by_id,
+        hard_delete_by_id,
+        bulk_delete,
+    ),
+    components(schemas(
+        UserDtoResponse,
+        UserProfileResponse,
+        ScoredUserDtoResponse,
+        IndexDtoResponse,
+        UserIdDtoResponse,
+        CreateUserDtoRequest,
+        UpdateUserDtoRequest,
+        UpdateUserDtoResponse,
+        BulkDeleteUsersDtoRequest,
+        BulkUpdateUsersDtoRequest,
+        BulkUpdateDtoResponse,
+        BulkCreateErrorDto,
+        BulkCreateDtoResponse,
+        UsersCountDtoResponse,
+        ErrorResponse,
+        ErrorCode,
+        MultiStatusItem,
+        MultiStatusResponse,
+    )),
+    tags((name = "users", description = "User management")),
+    security(("jwt" = [])),
+    modifiers(&SecuritySchemes)
+)]
+pub struct UsersApiDoc;
+
+/// Declares the bearer schemes referenced by `security`: the JWT of the `/users` scope
+/// (required only when `JWT_SECRET` is set) and `ADMIN_TOKEN` of the admin-only routes.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "jwt",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use actix_web::{
+        App,
+        dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+        http::header,
+        test,
+    };
+
+    use crate::{
+        shared::{
+            config::settings::{Settings, build_json_config},
+            dto::response::ErrorResponse,
+        },
+        users::users_memory_store::MemoryUserStore,
+    };
+
+    /// The `/users` routes over `store`, with the app data `main` registers.
+    fn test_app_with(
+        store: Arc<dyn UserStore>,
+        app_config: Settings,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        let json_config = build_json_config(app_config.max_json_bytes);
+        App::new()
+            .app_data(web::Data::from(store))
+            .app_data(web::Data::new(app_config))
+            .app_data(web::Data::new(UserReads::new()))
+            .app_data(json_config)
+            .service(web::scope("/users").configure(config))
+    }
+
+    /// [`test_app_with`] an empty memory store and the default settings.
+    fn test_app() -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        test_app_with(Arc::new(MemoryUserStore::new(false)), Settings::defaults())
+    }
+
+    #[actix_web::test]
+    async fn malformed_id_answers_400_before_data_access() {
+        let app = test::init_service(test_app()).await;
+
+        for req in [
+            test::TestRequest::get().uri("/users/notanid"),
+            test::TestRequest::get().uri("/users/notanid/profile"),
+            test::TestRequest::patch()
+                .uri("/users/notanid")
+                .set_json(serde_json::json!({ "first_name": "Mario" })),
+            test::TestRequest::delete().uri("/users/notanid"),
+        ] {
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body: ErrorResponse = test::read_body_json(res).await;
+            assert_eq!(body.code, Some(ErrorCode::ValidationFailed));
+            assert_eq!(body.message, INVALID_ID_MESSAGE);
+        }
+    }
+
+    #[actix_web::test]
+    async fn count_is_not_a_user_lookup() {
+        let app = test::init_service(test_app()).await;
+
+        let req = test::TestRequest::get().uri("/users/count").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: UsersCountDtoResponse = test::read_body_json(res).await;
+        assert_eq!(body.count, 0);
+
+        // registered after `{id}`, a literal path is still not captured by it
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Settings::defaults()))
+                .service(
+                    web::scope("/users")
+                        .service(get_by_id)
+                        .route("count", web::get().to(HttpResponse::NoContent)),
+                ),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/users/count").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn mistyped_bodies_answer_the_error_envelope() {
+        let app = test::init_service(test_app()).await;
+
+        let mistyped = serde_json::json!({ "first_name": 5 });
+        for req in [
+            test::TestRequest::post().uri("/users").set_json(&mistyped),
+            test::TestRequest::patch()
+                .uri("/users/6630f1c2a1b2c3d4e5f60718")
+                .set_json(&mistyped),
+            test::TestRequest::post()
+                .uri("/users/bulk")
+                .set_json([&mistyped]),
+        ] {
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body: ErrorResponse = test::read_body_json(res).await;
+            assert_eq!(body.code, Some(ErrorCode::ValidationFailed));
+            assert!(
+                body.message
+                    .starts_with("Invalid JSON body: invalid type: integer `5`, expected a string"),
+                "{}",
+                body.message
+            );
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(serde_json::json!({ "first_name": "Ada", "last_name": "Lovelace" }));
+        let body: ErrorResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        assert!(
+            body.message.contains("missing field `email`"),
+            "{}",
+            body.message
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(r#"{"first_name": "#);
+        let body: ErrorResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        assert!(
+            body.message.starts_with("Malformed JSON body: "),
+            "{}",
+            body.message
+        );
+    }
+
+    #[actix_web::test]
+    async fn unsupported_method_answers_405_with_allow() {
+        let app = test::init_service(test_app()).await;
+
+        for (method, uri, allow) in [
+            ("PUT", "/users", "GET, POST, PATCH, DELETE"),
+            ("POST", "/users/count", "GET"),
+            // literal paths are never taken for an id, whatever the method
+            ("PUT", "/users/count", "GET"),
+            ("GET", "/users/bulk", "POST"),
+            ("DELETE", "/users/_indexes", "GET"),
+            (
+                "POST",
+                "/users/6630f1c2a1b2c3d4e5f60718",
+                "GET, PUT, PATCH, DELETE",
+            ),
+            ("DELETE", "/users/6630f1c2a1b2c3d4e5f60718/profile", "GET"),
+        ] {
+            let req = test::TestRequest::default()
+                .method(method.parse().unwrap())
+                .uri(uri)
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(
+                res.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{} {}",
+                method,
+                uri
+            );
+            assert_eq!(res.headers().get(header::ALLOW).unwrap(), allow);
+        }
+    }
+
+    #[actix_web::test]
+    async fn bulk_create_reports_each_entry() {
+        let mut app_config = Settings::defaults();
+        app_config.max_bulk_size = 5;
+        let app = test::init_service(test_app_with(
+            Arc::new(MemoryUserStore::new(false)),
+            app_config,
+        ))
+        .await;
+        let user = |email: &str| serde_json::json!({ "first_name": "Ada", "last_name": "Lovelace", "email": email });
+
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(user("taken@example.com"));
+        assert!(
+            test::call_service(&app, req.to_request())
+                .await
+                .status()
+                .is_success()
+        );
+
+        let req = test::TestRequest::post().uri("/users/bulk").set_json([
+            user("a@example.com"),
+            user("not-an-email"),
+            user("A@example.com "),
+            user("Taken@example.com"),
+            user("b@example.com"),
+        ]);
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: BulkCreateDtoResponse = test::read_body_json(res).await;
+        assert_eq!(body.inserted.len(), 2);
+        let errors: Vec<(usize, &str)> = body
+            .errors
+            .iter()
+            .map(|error| (error.index, error.message.as_str()))
+            .collect();
+        assert_eq!(errors[0].0, 1);
+        assert!(errors[0].1.starts_with("Invalid fields"));
+        assert_eq!(
+            errors[1..],
+            [(2, "Duplicate email in request"), (3, "Already exists")]
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/users/bulk")
+            .set_json(vec![user("c@example.com"); 6]);
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn failed_email_lookups_are_not_taken_emails() {
+        let store = MemoryUserStore::new(false).failing_email_lookups();
+        let app = test::init_service(test_app_with(Arc::new(store), Settings::defaults())).await;
+
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(serde_json::json!({
+                "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com"
+            }));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: ErrorResponse = test::read_body_json(res).await;
+        assert_ne!(body.code, Some(ErrorCode::EmailExists));
+    }
+
+    #[actix_web::test]
+    async fn unknown_ids_answer_404() {
+        let app = test::init_service(test_app()).await;
+
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(serde_json::json!({
+                "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com"
+            }));
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let location = res.headers().get(header::LOCATION).unwrap().clone();
+        let created: UserIdDtoResponse = test::read_body_json(res).await;
+        assert_eq!(location, format!("/users/{}", created.id).as_str());
+
+        let delete = |id: &str| test::TestRequest::delete().uri(&format!("/users/{}", id));
+        let res = test::call_service(&app, delete(&created.id).to_request()).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        // every route answers a missing user alike, DELETE included
+        for req in [
+            delete(&created.id),
+            delete("6630f1c2a1b2c3d4e5f60718"),
+            test::TestRequest::get().uri("/users/6630f1c2a1b2c3d4e5f60718"),
+            test::TestRequest::patch()
+                .uri("/users/6630f1c2a1b2c3d4e5f60718")
+                .set_json(serde_json::json!({ "first_name": "Mario" })),
+        ] {
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            let body: ErrorResponse = test::read_body_json(res).await;
+            assert_eq!(body.code, Some(ErrorCode::UserNotFound));
+        }
+    }
+
+    #[actix_web::test]
+    async fn put_replaces_the_whole_user() {
+        let app = test::init_service(test_app()).await;
+        let mut ids = Vec::new();
+        for email in ["ada@example.com", "alan@example.com"] {
+            let req = test::TestRequest::post()
+                .uri("/users")
+                .set_json(serde_json::json!({
+                    "first_name": "Ada", "last_name": "Lovelace", "email": email, "age": 36
+                }));
+            let created: UserIdDtoResponse =
+                test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+            ids.push(created.id);
+        }
+        let put = |id: &str, email: &str| {
+            test::TestRequest::put()
+                .uri(&format!("/users/{}", id))
+                .set_json(serde_json::json!({
+                    "first_name": "Augusta", "last_name": "King", "email": email
+                }))
+                .to_request()
+        };
+
+        // keeping its own email is fine, the missing age is cleared
+        let res = test::call_service(&app, put(&ids[0], "Ada@example.com")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let user: UserDtoResponse = test::read_body_json(res).await;
+        assert_eq!(
+            (
+                user.id.as_str(),
+                user.first_name.as_str(),
+                user.email.as_str()
+            ),
+            (ids[0].as_str(), "Augusta", "ada@example.com")
+        );
+        assert_eq!(user.age, None);
+
+        let res = test::call_service(&app, put(&ids[0], "alan@example.com")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorResponse = test::read_body_json(res).await;
+        assert_eq!(body.code, Some(ErrorCode::EmailExists));
+
+        let res = test::call_service(&app, put("6630f1c2a1b2c3d4e5f60718", "x@example.com")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn timestamps_follow_creation_and_changes() {
+        let app = test::init_service(test_app()).await;
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(serde_json::json!({
+                "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com"
+            }));
+        let created: UserIdDtoResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        let uri = format!("/users/{}", created.id);
+        let patch = |first_name: &str| {
+            test::TestRequest::patch()
+                .uri(&uri)
+                .set_json(serde_json::json!({ "first_name": first_name }))
+                .to_request()
+        };
+
+        // a no-op update leaves `updated_at` alone
+        let res = test::call_service(&app, patch("Ada")).await;
+        assert_eq!(res.headers().get(MODIFIED_HEADER).unwrap(), "false");
+        let updated: UpdateUserDtoResponse = test::read_body_json(res).await;
+        assert!(updated.changed.is_empty());
+        assert_eq!(updated.user.updated_at, updated.user.created_at);
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let res = test::call_service(&app, patch("Augusta")).await;
+        assert_eq!(res.headers().get(MODIFIED_HEADER).unwrap(), "true");
+        let updated: UpdateUserDtoResponse = test::read_body_json(res).await;
+        assert_eq!(updated.changed, ["first_name"]);
+        let user = updated.user;
+        assert_eq!(user.first_name, "Augusta");
+        assert!(user.updated_at > user.created_at);
+        let created_at = user.created_at;
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let req = test::TestRequest::put()
+            .uri(&uri)
+            .set_json(serde_json::json!({
+                "first_name": "Ada", "last_name": "King", "email": "ada@example.com"
+            }));
+        let replaced: UserDtoResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        assert_eq!(replaced.created_at, created_at);
+        assert!(replaced.updated_at > user.updated_at);
+    }
+
+    #[actix_web::test]
+    async fn patch_rejects_blank_names_and_trims_the_others() {
+        let app = test::init_service(test_app()).await;
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(serde_json::json!({
+                "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com"
+            }));
+        let created: UserIdDtoResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        let uri = format!("/users/{}", created.id);
+        let patch = |body: serde_json::Value| {
+            test::TestRequest::patch()
+                .uri(&uri)
+                .set_json(body)
+                .to_request()
+        };
+
+        for body in [
+            serde_json::json!({ "first_name": "   " }),
+            serde_json::json!({ "first_name": "" }),
+            serde_json::json!({ "first_name": "Ada", "last_name": "\t" }),
+        ] {
+            let res = test::call_service(&app, patch(body.clone())).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", body);
+        }
+
+        // surrounding spaces are not a change
+        let res =
+            test::call_service(&app, patch(serde_json::json!({ "first_name": " Ada " }))).await;
+        assert_eq!(res.headers().get(MODIFIED_HEADER).unwrap(), "false");
+
+        let res = test::call_service(
+            &app,
+            patch(serde_json::json!({ "first_name": "  Augusta ", "last_name": "King" })),
+        )
+        .await;
+        let updated: UpdateUserDtoResponse = test::read_body_json(res).await;
+        assert_eq!(updated.changed, ["first_name", "last_name"]);
+        assert_eq!(updated.user.first_name, "Augusta");
+        assert_eq!(updated.user.last_name, "King");
+    }
+
+    #[actix_web::test]
+    async fn deleted_users_are_kept_until_removed_for_good() {
+        let mut app_config = Settings::defaults();
+        app_config.admin_token = Some("admin-token".into());
+        let app = test::init_service(test_app_with(
+            Arc::new(MemoryUserStore::new(false)),
+            app_config,
+        ))
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(serde_json::json!({
+                "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com"
+            }));
+        let created: UserIdDtoResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        let uri = format!("/users/{}", created.id);
+        let call = |req: test::TestRequest| test::call_service(&app, req.to_request());
+        let as_admin =
+            |req: test::TestRequest| req.insert_header(("Authorization", "Bearer admin-token"));
+
+        let res = call(test::TestRequest::delete().uri(&uri)).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        for req in [
+            test::TestRequest::get().uri(&uri),
+            test::TestRequest::delete().uri(&uri),
+        ] {
+            assert_eq!(call(req).await.status(), StatusCode::NOT_FOUND);
+        }
+        let users: Vec<UserDtoResponse> =
+            test::read_body_json(call(test::TestRequest::get().uri("/users")).await).await;
+        assert!(users.is_empty());
+
+        let listed = "/users?include_deleted=true";
+        let res = call(test::TestRequest::get().uri(listed)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let users: Vec<UserDtoResponse> =
+            test::read_body_json(call(as_admin(test::TestRequest::get().uri(listed))).await).await;
+        assert_eq!(users.len(), 1);
+        assert!(users[0].deleted_at.is_some());
+
+        let permanent = format!("{}/permanent", uri);
+        let res = call(test::TestRequest::delete().uri(&permanent)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = call(as_admin(test::TestRequest::delete().uri(&permanent))).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = call(as_admin(test::TestRequest::get().uri(listed))).await;
+        let users: Vec<UserDtoResponse> = test::read_body_json(res).await;
+        assert!(users.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn indexes_are_listed_to_admins_and_not_taken_for_an_id() {
+        let mut app_config = Settings::defaults();
+        app_config.admin_token = Some("admin-token".into());
+        let app = test::init_service(test_app_with(
+            Arc::new(MemoryUserStore::new(false)),
+            app_config,
+        ))
+        .await;
+
+        let req = test::TestRequest::get().uri("/users/_indexes").to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let req = test::TestRequest::get()
+            .uri("/users/_indexes")
+            .insert_header(("Authorization", "Bearer admin-token"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let indexes: Vec<IndexDtoResponse> = test::read_body_json(res).await;
+        assert!(indexes.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn list_and_count_share_the_filter() {
+        let app = test::init_service(test_app()).await;
+        for (last_name, email, age) in [
+            ("Lovelace", "ada@example.com", 36),
+            ("Turing", "alan@example.com", 41),
+            ("Hopper", "grace@example.com", 17),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/users")
+                .set_json(serde_json::json!({
+                    "first_name": "X", "last_name": last_name, "email": email, "age": age
+                }));
+            test::call_service(&app, req.to_request()).await;
+        }
+
+        for (filter, expected) in [
+            ("", 3),
+            ("?min_age=18", 2),
+            ("?email=%20ADA@example.com", 1),
+            ("?last_name=Turing&min_age=42", 0),
+        ] {
+            let req = test::TestRequest::get().uri(&format!("/users{}", filter));
+            let users: Vec<UserDtoResponse> =
+                test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+            assert_eq!(users.len(), expected, "GET /users{}", filter);
+
+            let req = test::TestRequest::get().uri(&format!("/users/count{}", filter));
+            let counted: UsersCountDtoResponse =
+                test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+            assert_eq!(
+                counted.count as usize, expected,
+                "GET /users/count{}",
+                filter
+            );
+        }
+
+        let req = test::TestRequest::get().uri("/users/count?nickname=ada");
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn search_matches_names_and_email_literally() {
+        let app = test::init_service(test_app()).await;
+        for (first_name, email) in [
+            ("Ada", "countess@example.com"),
+            ("Alan", "alan.t@example.com"),
+            ("Grace", "amazing.grace@example.org"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/users")
+                .set_json(serde_json::json!({
+                    "first_name": first_name, "last_name": "X", "email": email
+                }));
+            test::call_service(&app, req.to_request()).await;
+        }
+
+        for (q, expected) in [
+            ("ADA", 1),
+            ("grace", 1),
+            ("example", 3),
+            ("example&limit=2", 2),
+            ("example&skip=2", 1),
+            // metacharacters are matched literally
+            ("n.t", 1),
+            ("a%2B", 0),
+            (".*", 0),
+        ] {
+            let req = test::TestRequest::get().uri(&format!("/users/search?q={}", q));
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK, "q={}", q);
+            let users: Vec<UserDtoResponse> = test::read_body_json(res).await;
+            assert_eq!(users.len(), expected, "q={}", q);
+        }
+
+        let req = test::TestRequest::get().uri("/users/search?q=%20");
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn fields_restrict_the_listed_users() {
+        let app = test::init_service(test_app()).await;
+        let req = test::TestRequest::post()
+            .uri("/users")
+            .set_json(serde_json::json!({
+                "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com"
+            }));
+        let created: UserIdDtoResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+
+        let req = test::TestRequest::get().uri("/users?fields=id,email");
+        let users: Vec<Value> =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        assert_eq!(
+            users,
+            [serde_json::json!({ "id": created.id, "email": "ada@example.com" })]
+        );
+
+        for uri in ["/users?fields=id,password", "/users/count?fields=id"] {
+            let req = test::TestRequest::get().uri(uri);
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn get_by_id_answers_304_to_a_matching_etag() {
+        let app = test::init_service(test_app()).await;
+        let req = test::TestRequest::post().uri("/users").set_json(
+            serde_json::json!({ "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com" }),
+        );
+        let created: UserIdDtoResponse =
+            test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+        let uri = format!("/users/{}", created.id);
+        let get = |etag: Option<&HeaderValue>| {
+            let mut req = test::TestRequest::get().uri(&uri);
+            if let Some(etag) = etag {
+                req = req.insert_header((header::IF_NONE_MATCH, etag.clone()));
+            }
+            req.to_request()
+        };
+
+        let res = test::call_service(&app, get(None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers().get(header::ETAG).unwrap().clone();
+        assert!(etag.to_str().unwrap().starts_with("W/\""));
+        let user: UserDtoResponse = test::read_body_json(res).await;
+        assert_eq!(etag.to_str().unwrap(), etag_for(&user).to_string());
+
+        let res = test::call_service(&app, get(Some(&etag))).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get(header::ETAG), Some(&etag));
+        assert!(test::read_body(res).await.is_empty());
+
+        // an update changes the representation, so the tag
+        let req = test::TestRequest::patch()
+            .uri(&uri)
+            .set_json(serde_json::json!({ "first_name": "Augusta" }));
+        test::call_service(&app, req.to_request()).await;
+        let res = test::call_service(&app, get(Some(&etag))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_ne!(res.headers().get(header::ETAG), Some(&etag));
+    }
+
+    #[actix_web::test]
+    async fn get_all_pages_forward_with_the_next_cursor() {
+        let store: Arc<dyn UserStore> = Arc::new(MemoryUserStore::new(false));
+        let scope = StoreScope {
+            database: "test".into(),
+            comment: None,
+        };
+        users_service::seed(store.as_ref(), &scope, 5)
+            .await
+            .unwrap();
+        let mut app_config = Settings::defaults();
+        app_config.database = scope.database;
+        let app = test::init_service(test_app_with(store, app_config)).await;
+
+        let mut emails = Vec::new();
+        let mut uri = "/users?limit=2".to_string();
+        loop {
+            let res =
+                test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            let cursor = res
+                .headers()
+                .get(NEXT_CURSOR_HEADER)
+                .map(|v| v.to_str().unwrap().to_owned());
+            let page: Vec<UserDtoResponse> = test::read_body_json(res).await;
+            emails.extend(page.into_iter().map(|user| user.email));
+            match cursor {
+                Some(cursor) => uri = format!("/users?limit=2&after={}", cursor),
+                None => break,
+            }
+        }
+        // ids grow with insertion order, the last page is not full
+        let expected: Vec<String> = (1..=5).map(|n| format!("demo{}@example.com", n)).collect();
+        assert_eq!(emails, expected);
+
+        for uri in [
+            "/users?after=notanid",
+            "/users?after=6630f1c2a1b2c3d4e5f60718&skip=1",
+            "/users?limit=0",
+            "/users/count?limit=2",
+        ] {
+            let res =
+                test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn patch_rejects_empty_bodies_and_taken_emails() {
+        let app = test::init_service(test_app()).await;
+        let mut ids = Vec::new();
+        for email in ["ada@example.com", "alan@example.com"] {
+            let req = test::TestRequest::post().uri("/users").set_json(
+                serde_json::json!({ "first_name": "X", "last_name": "Y", "email": email }),
+            );
+            let created: UserIdDtoResponse =
+                test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+            ids.push(created.id);
+        }
+        let patch = |body: serde_json::Value| {
+            test::TestRequest::patch()
+                .uri(&format!("/users/{}", ids[0]))
+                .set_json(body)
+                .to_request()
+        };
+
+        for (body, code, message) in [
+            (
+                serde_json::json!({}),
+                ErrorCode::ValidationFailed,
+                "No fields to update",
+            ),
+            (
+                serde_json::json!({ "email": " ALAN@example.com" }),
+                ErrorCode::EmailExists,
+                "Already exists",
+            ),
+        ] {
+            let res = test::call_service(&app, patch(body)).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let err: ErrorResponse = test::read_body_json(res).await;
+            assert_eq!((err.code, err.message.as_str()), (Some(code), message));
+        }
+
+        // keeping its own email is not a conflict
+        let res = test::call_service(
+            &app,
+            patch(serde_json::json!({ "email": "ada@example.com" })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
 }
@@ -0,0 +1,153 @@
+//! ZIP export of the users registry, built while it is sent.
+//!
+//! Each user becomes one `{id}.json` entry. Entries are compressed and flushed to the
+//! response as soon as their user is read from the store, so memory stays bounded by one
+//! user (plus the archive directory, a few dozen bytes per entry).
+use std::{cell::RefCell, io, io::Write, mem, rc::Rc};
+
+use actix_web::web::Bytes;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use log::error;
+use zip::{
+    CompressionMethod, ZipWriter,
+    write::{SimpleFileOptions, StreamWriter},
+};
+
+use crate::users::{dto::UserDtoResponse, users_model::User};
+
+/// File name proposed to the client (`Content-Disposition`).
+pub const EXPORT_FILE_NAME: &str = "users.zip";
+
+/// Archive bytes written but not sent yet, shared with the `ZipWriter` that fills it.
+#[derive(Clone, Default)]
+struct Pending(Rc<RefCell<Vec<u8>>>);
+
+impl Pending {
+    fn take(&self) -> Bytes {
+        Bytes::from(mem::take(&mut *self.0.borrow_mut()))
+    }
+}
+
+impl Write for Pending {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Export in progress, the stream state turns `None` once the archive is complete or failed.
+struct Export {
+    users: BoxStream<'static, Result<User, String>>,
+    zip: ZipWriter<StreamWriter<Pending>>,
+    pending: Pending,
+}
+
+fn add_user(zip: &mut ZipWriter<StreamWriter<Pending>>, user: User) -> io::Result<()> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let name = format!("{}.json", user._id.to_hex());
+    let json = serde_json::to_vec_pretty(&UserDtoResponse::from(user)).map_err(io::Error::other)?;
+    zip.start_file(name, options).map_err(io::Error::other)?;
+    zip.write_all(&json)
+}
+
+/// Turns a users stream into the chunks of a ZIP archive.
+///
+/// A store error ends the body with an error, the client sees a truncated download
+/// rather than a valid archive missing users.
+pub fn zip_stream(
+    users: BoxStream<'static, Result<User, String>>,
+) -> impl Stream<Item = Result<Bytes, io::Error>> + 'static {
+    let pending = Pending::default();
+    let export = Export {
+        users,
+        zip: ZipWriter::new_stream(pending.clone()),
+        pending,
+    };
+
+    stream::unfold(Some(export), |export| async move {
+        let mut export = export?;
+        match export.users.next().await {
+            Some(Ok(user)) => match add_user(&mut export.zip, user) {
+                Ok(()) => Some((Ok(export.pending.take()), Some(export))),
+                Err(err) => {
+                    error!("Users export failed: {}", err);
+                    Some((Err(err), None))
+                }
+            },
+            Some(Err(err)) => {
+                error!("Users export aborted: {}", err);
+                Some((Err(io::Error::other(err)), None))
+            }
+            None => match export.zip.finish() {
+                Ok(_) => Some((Ok(export.pending.take()), None)),
+                Err(err) => {
+                    error!("Users export failed: {}", err);
+                    Some((Err(io::Error::other(err)), None))
+                }
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Cursor, Read};
+
+    use mongodb::bson::oid::ObjectId;
+    use zip::ZipArchive;
+
+    fn user(first_name: &str) -> User {
+        User {
+            _id: ObjectId::new(),
+            first_name: first_name.into(),
+            last_name: "Rossi".into(),
+            email: format!("{}@example.com", first_name.to_lowercase()),
+            age: Some(30),
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+        }
+    }
+
+    async fn collect(users: Vec<Result<User, String>>) -> Result<Vec<u8>, io::Error> {
+        let mut chunks = Box::pin(zip_stream(stream::iter(users).boxed()));
+        let mut body = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(body)
+    }
+
+    #[actix_web::test]
+    async fn archive_holds_one_json_per_user() {
+        let users = vec![user("Mario"), user("Luigi")];
+        let ids: Vec<String> = users.iter().map(|user| user._id.to_hex()).collect();
+
+        let body = collect(users.into_iter().map(Ok).collect()).await.unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(body)).unwrap();
+        assert_eq!(archive.len(), 2);
+
+        for id in ids {
+            let mut json = String::new();
+            archive
+                .by_name(&format!("{}.json", id))
+                .unwrap()
+                .read_to_string(&mut json)
+                .unwrap();
+            let dto: UserDtoResponse = serde_json::from_str(&json).unwrap();
+            assert_eq!(dto.id, id);
+        }
+    }
+
+    #[actix_web::test]
+    async fn store_error_fails_the_body() {
+        let users = vec![Ok(user("Mario")), Err("Database query error".to_string())];
+        assert!(collect(users).await.is_err());
+    }
+}
@@ -0,0 +1,790 @@
+//! In-memory users storage backend (`STORAGE_BACKEND=memory`).
+//!
+//! Users live in process memory, one registry per database name, so the starter runs
+//! without MongoDB for demos, tests and small single-instance deployments. Data is lost on
+//! restart and never shared between instances.
+//!
+//! It follows the MongoDB behaviour the handlers rely on: filters match like
+//! `users_repository::build_filter`, missing values sort first with `_id` as tie-breaker,
+//! emails are unique and updates report matched/modified counts. Text search matches whole
+//! words of the names, case-insensitively, scores only approximate MongoDB `textScore`.
+//! Soft-deleted users keep their email, like the default unique email index, unless
+//! `EMAIL_REUSE_AFTER_DELETE` is on: emails are then unique among active users only.
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, PoisonError},
+};
+
+use futures_util::{
+    future::{self, BoxFuture, FutureExt},
+    stream::{self, BoxStream, StreamExt},
+};
+use mongodb::bson::{DateTime, Document, from_document, oid::ObjectId, to_document};
+use uuid::Uuid;
+
+use crate::{
+    shared::{config::settings::SortConfig, error::AppError},
+    users::{
+        dto::UsersFilterQuery,
+        users_model::{IndexInfo, IndexesReport, ScoredUser, User, UserProfile},
+        users_store::{InsertFailure, Page, StoreScope, UpdateCounts, UserStore},
+    },
+};
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Users of one database, ordered by id.
+type Users = BTreeMap<ObjectId, User>;
+
+/// In-memory backend, share it as `web::Data<dyn UserStore>`.
+#[derive(Default)]
+pub struct MemoryUserStore {
+    databases: Mutex<HashMap<String, Users>>,
+    email_reuse_after_delete: bool,
+    /// Test double of a database outage, see [`MemoryUserStore::failing_email_lookups`].
+    #[cfg(test)]
+    failing_email_lookups: bool,
+}
+
+impl MemoryUserStore {
+    /// `email_reuse_after_delete` frees the email of deleted users, like the
+    /// `email_unique_active` index of the MongoDB backend.
+    pub fn new(email_reuse_after_delete: bool) -> Self {
+        MemoryUserStore {
+            email_reuse_after_delete,
+            ..Default::default()
+        }
+    }
+
+    /// The same store with every `find_by_email` failing, like during a database outage.
+    #[cfg(test)]
+    pub fn failing_email_lookups(self) -> Self {
+        MemoryUserStore {
+            failing_email_lookups: true,
+            ..self
+        }
+    }
+
+    /// Runs `f` on the users of the scope database, created empty on first use.
+    fn with_users<R>(&self, scope: &StoreScope, f: impl FnOnce(&mut Users) -> R) -> R {
+        let mut databases = self
+            .databases
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        f(databases.entry(scope.database.clone()).or_default())
+    }
+}
+
+/// Lowercase words of `text`, split on anything not alphanumeric.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Occurrences of the `terms` in the names, 0 when the user does not match.
+fn text_score(user: &User, terms: &[String]) -> f64 {
+    words(&user.first_name)
+        .chain(words(&user.last_name))
+        .filter(|word| terms.contains(word))
+        .count() as f64
+}
+
+/// Users not soft-deleted.
+fn active(users: &Users) -> impl Iterator<Item = &User> {
+    users.values().filter(|user| user.deleted_at.is_none())
+}
+
+/// The user `id` unless it is missing or soft-deleted.
+fn find_active(users: &Users, id: ObjectId) -> Option<&User> {
+    users.get(&id).filter(|user| user.deleted_at.is_none())
+}
+
+/// Same semantics as the MongoDB filter of `users_repository::build_filter`.
+fn matches(user: &User, query: &UsersFilterQuery) -> bool {
+    (query.include_deleted || user.deleted_at.is_none())
+        && query
+            .first_name
+            .as_ref()
+            .is_none_or(|v| *v == user.first_name)
+        && query
+            .last_name
+            .as_ref()
+            .is_none_or(|v| *v == user.last_name)
+        && query.email.as_ref().is_none_or(|v| *v == user.email)
+        && query.age.is_none_or(|v| user.age == Some(v))
+        && query
+            .min_age
+            .is_none_or(|min| user.age.is_some_and(|age| age >= min))
+        && query
+            .max_age
+            .is_none_or(|max| user.age.is_some_and(|age| age <= max))
+}
+
+/// Compares two users on `field`, a missing age sorts first like a MongoDB null.
+///
+/// Fields `User` does not map compare equal, leaving the order to the `_id` tie-breaker.
+fn compare(a: &User, b: &User, field: &str) -> Ordering {
+    match field {
+        "first_name" => a.first_name.cmp(&b.first_name),
+        "last_name" => a.last_name.cmp(&b.last_name),
+        "email" => a.email.cmp(&b.email),
+        "age" => a.age.cmp(&b.age),
+        _ => Ordering::Equal,
+    }
+}
+
+/// Case-insensitive `users_repository::build_search_filter`, `term` being lowercase.
+fn contains_term(user: &User, term: &str) -> bool {
+    [&user.first_name, &user.last_name, &user.email]
+        .iter()
+        .any(|value| value.to_lowercase().contains(term))
+}
+
+/// Orders `found` by `sort` then `_id` and keeps the `page` slice.
+fn sort_and_page(mut found: Vec<User>, sort: &SortConfig, page: Page) -> Vec<User> {
+    if let Some(after) = page.after {
+        found.retain(|user| user._id > after);
+        found.sort_by_key(|user| user._id);
+    } else {
+        found.sort_by(|a, b| {
+            let order = compare(a, b, &sort.field).then(a._id.cmp(&b._id));
+            if sort.descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+    found
+        .into_iter()
+        .skip(usize::try_from(page.skip).unwrap_or(usize::MAX))
+        .take(page.limit.map_or(usize::MAX, |limit| {
+            usize::try_from(limit).unwrap_or(usize::MAX)
+        }))
+        .collect()
+}
+
+/// True when another user than `id` already holds `email`, only active users count when
+/// `active_only`.
+fn email_taken(users: &Users, email: &str, id: ObjectId, active_only: bool) -> bool {
+    users.values().any(|user| {
+        user.email == email && user._id != id && !(active_only && user.deleted_at.is_some())
+    })
+}
+
+/// Applies `set` to `user` like a MongoDB `$set`, `None` when nothing changes.
+fn apply_set(
+    users: &Users,
+    user: &User,
+    set: &Document,
+    now: DateTime,
+    active_only: bool,
+) -> Result<Option<User>, String> {
+    if let Ok(email) = set.get_str("email")
+        && email_taken(users, email, user._id, active_only)
+    {
+        return Err(format!("Email {} already exists", email));
+    }
+
+    let current = to_document(user).map_err(|err| err.to_string())?;
+    let mut updated = current.clone();
+    updated.extend(set.clone());
+    if updated == current {
+        return Ok(None);
+    }
+    let mut updated: User = from_document(updated).map_err(|err| err.to_string())?;
+    updated.updated_at = Some(now);
+    Ok(Some(updated))
+}
+
+/// Sets `deleted_at` on the user `id`, false when it is missing or already deleted.
+fn soft_delete(users: &mut Users, id: ObjectId) -> bool {
+    match users.get_mut(&id) {
+        Some(user) if user.deleted_at.is_none() => {
+            user.deleted_at = Some(DateTime::now());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Computes the fields of `users_repository::find_profile`.
+fn profile(user: User) -> UserProfile {
+    let created_at = user.created_at();
+    let age_group = match user.age {
+        None => "unknown",
+        Some(age) if age < 18 => "minor",
+        Some(age) if age < 30 => "18-29",
+        Some(age) if age < 50 => "30-49",
+        Some(age) if age < 65 => "50-64",
+        Some(_) => "65+",
+    };
+
+    UserProfile {
+        full_name: format!("{} {}", user.first_name, user.last_name)
+            .trim()
+            .to_string(),
+        age_group: age_group.into(),
+        account_age_days: (DateTime::now().timestamp_millis() - created_at.timestamp_millis())
+            / DAY_MILLIS,
+        created_at,
+        user,
+    }
+}
+
+impl UserStore for MemoryUserStore {
+    fn list<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        sort: &'a SortConfig,
+        page: Page,
+    ) -> BoxFuture<'a, Result<Vec<User>, String>> {
+        let found: Vec<User> = self.with_users(scope, |users| {
+            users
+                .values()
+                .filter(|user| matches(user, filter))
+                .cloned()
+                .collect()
+        });
+
+        future::ready(Ok(sort_and_page(found, sort, page))).boxed()
+    }
+
+    /// Streams a snapshot, users added or removed meanwhile are not reflected.
+    fn stream_all(&self, scope: &StoreScope) -> BoxStream<'static, Result<User, String>> {
+        // BTreeMap iterates in `_id` order
+        let users: Vec<User> = self.with_users(scope, |users| active(users).cloned().collect());
+        stream::iter(users.into_iter().map(Ok)).boxed()
+    }
+
+    fn count<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        limit: Option<u64>,
+    ) -> BoxFuture<'a, Result<u64, String>> {
+        let count = self.with_users(scope, |users| {
+            users.values().filter(|user| matches(user, filter)).count() as u64
+        });
+        future::ready(Ok(limit.map_or(count, |limit| count.min(limit)))).boxed()
+    }
+
+    fn text_search<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        query: &'a str,
+        limit: u64,
+    ) -> BoxFuture<'a, Result<Option<Vec<ScoredUser>>, String>> {
+        let terms: Vec<String> = words(query).collect();
+        let mut found: Vec<ScoredUser> = self.with_users(scope, |users| {
+            active(users)
+                .map(|user| ScoredUser {
+                    user: user.clone(),
+                    score: text_score(user, &terms),
+                })
+                .filter(|found| found.score > 0.0)
+                .collect()
+        });
+        // Stable sort, equal scores stay in `_id` order
+        found.sort_by(|a, b| b.score.total_cmp(&a.score));
+        found.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+        future::ready(Ok(Some(found))).boxed()
+    }
+
+    fn search<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        term: &'a str,
+        sort: &'a SortConfig,
+        page: Page,
+    ) -> BoxFuture<'a, Result<Vec<User>, String>> {
+        let term = term.to_lowercase();
+        let found: Vec<User> = self.with_users(scope, |users| {
+            active(users)
+                .filter(|user| contains_term(user, &term))
+                .cloned()
+                .collect()
+        });
+
+        future::ready(Ok(sort_and_page(found, sort, page))).boxed()
+    }
+
+    fn estimated_count<'a>(&'a self, scope: &'a StoreScope) -> BoxFuture<'a, Result<u64, String>> {
+        let count = self.with_users(scope, |users| users.len() as u64);
+        future::ready(Ok(count)).boxed()
+    }
+
+    fn find_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<User>, String>> {
+        let user = self.with_users(scope, |users| find_active(users, id).cloned());
+        future::ready(Ok(user)).boxed()
+    }
+
+    fn find_by_email<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        email: &'a str,
+    ) -> BoxFuture<'a, Result<Option<User>, String>> {
+        #[cfg(test)]
+        if self.failing_email_lookups {
+            return future::ready(Err("Database unreachable".into())).boxed();
+        }
+        let user = self.with_users(scope, |users| {
+            active(users).find(|user| user.email == email).cloned()
+        });
+        future::ready(Ok(user)).boxed()
+    }
+
+    fn find_profile<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<UserProfile>, String>> {
+        let user = self.with_users(scope, |users| find_active(users, id).cloned());
+        future::ready(Ok(user.map(profile))).boxed()
+    }
+
+    fn find_raw_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<Document>, String>> {
+        let user = self.with_users(scope, |users| users.get(&id).cloned());
+        let document = user
+            .map(|user| to_document(&user))
+            .transpose()
+            .map_err(|err| err.to_string());
+        future::ready(document).boxed()
+    }
+
+    fn find_random<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<Option<User>, String>> {
+        let user = self.with_users(scope, |users| {
+            let users: Vec<&User> = active(users).collect();
+            if users.is_empty() {
+                return None;
+            }
+            // v4 uuids are random, good enough to pick a sample without a rand dependency
+            let index = (Uuid::new_v4().as_u128() % users.len() as u128) as usize;
+            Some(users[index].clone())
+        });
+        future::ready(Ok(user)).boxed()
+    }
+
+    fn find_existing_ids<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        ids: &'a [ObjectId],
+    ) -> BoxFuture<'a, Result<Vec<ObjectId>, String>> {
+        let mut existing: Vec<ObjectId> = self.with_users(scope, |users| {
+            ids.iter()
+                .filter(|id| find_active(users, **id).is_some())
+                .copied()
+                .collect()
+        });
+        // distinct values, like the MongoDB implementation
+        existing.sort();
+        existing.dedup();
+        future::ready(Ok(existing)).boxed()
+    }
+
+    fn insert<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        user: User,
+    ) -> BoxFuture<'a, Result<ObjectId, AppError>> {
+        let result = self.with_users(scope, |users| {
+            if users.contains_key(&user._id) {
+                return Err(AppError::Duplicate(format!(
+                    "User {} already exists",
+                    user._id
+                )));
+            }
+            if email_taken(users, &user.email, user._id, self.email_reuse_after_delete) {
+                return Err(AppError::Duplicate(format!(
+                    "Email {} already exists",
+                    user.email
+                )));
+            }
+            let id = user._id;
+            users.insert(id, user);
+            Ok(id)
+        });
+        future::ready(result).boxed()
+    }
+
+    fn insert_many<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        users: Vec<User>,
+    ) -> BoxFuture<'a, Result<Vec<InsertFailure>, AppError>> {
+        let failed = self.with_users(scope, |stored| {
+            let mut failed = Vec::new();
+            for (index, user) in users.into_iter().enumerate() {
+                if stored.contains_key(&user._id)
+                    || email_taken(stored, &user.email, user._id, self.email_reuse_after_delete)
+                {
+                    failed.push(InsertFailure {
+                        index,
+                        duplicate: true,
+                    });
+                } else {
+                    stored.insert(user._id, user);
+                }
+            }
+            failed
+        });
+        future::ready(Ok(failed)).boxed()
+    }
+
+    fn replace_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        user: User,
+    ) -> BoxFuture<'a, Result<Option<User>, AppError>> {
+        let result = self.with_users(scope, |users| {
+            let Some(stored) = find_active(users, user._id) else {
+                return Ok(None);
+            };
+            if email_taken(users, &user.email, user._id, self.email_reuse_after_delete) {
+                return Err(AppError::Duplicate(format!(
+                    "Email {} already exists",
+                    user.email
+                )));
+            }
+            let user = User {
+                created_at: stored.created_at,
+                ..user
+            };
+            users.insert(user._id, user.clone());
+            Ok(Some(user))
+        });
+        future::ready(result).boxed()
+    }
+
+    fn update_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+        set: Document,
+        now: DateTime,
+    ) -> BoxFuture<'a, Result<Option<User>, AppError>> {
+        let result = self.with_users(scope, |users| {
+            let Some(user) = find_active(users, id) else {
+                return Ok(None);
+            };
+            if let Ok(email) = set.get_str("email")
+                && email_taken(users, email, id, self.email_reuse_after_delete)
+            {
+                return Err(AppError::Duplicate(format!(
+                    "Email {} already exists",
+                    email
+                )));
+            }
+            let before = user.clone();
+            if let Some(updated) = apply_set(users, user, &set, now, self.email_reuse_after_delete)
+                .map_err(AppError::Validation)?
+            {
+                users.insert(id, updated);
+            }
+            Ok(Some(before))
+        });
+        future::ready(result).boxed()
+    }
+
+    fn update_many<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        set: Document,
+    ) -> BoxFuture<'a, Result<UpdateCounts, String>> {
+        let result = self.with_users(scope, |users| {
+            let ids: Vec<ObjectId> = users
+                .values()
+                .filter(|user| matches(user, filter))
+                .map(|user| user._id)
+                .collect();
+
+            let now = DateTime::now();
+            let mut counts = UpdateCounts::default();
+            for id in ids {
+                counts.matched += 1;
+                if let Some(updated) =
+                    apply_set(users, &users[&id], &set, now, self.email_reuse_after_delete)?
+                {
+                    users.insert(id, updated);
+                    counts.modified += 1;
+                }
+            }
+            Ok(counts)
+        });
+        future::ready(result).boxed()
+    }
+
+    fn delete_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        let deleted = self.with_users(scope, |users| soft_delete(users, id));
+        let result = if deleted {
+            Ok(())
+        } else {
+            Err(AppError::NotFound(format!("User not found for id {}", id)))
+        };
+        future::ready(result).boxed()
+    }
+
+    fn hard_delete_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        let result = match self.with_users(scope, |users| users.remove(&id)) {
+            Some(_) => Ok(()),
+            None => Err(AppError::NotFound(format!("User not found for id {}", id))),
+        };
+        future::ready(result).boxed()
+    }
+
+    fn delete_many_by_ids<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        ids: &'a [ObjectId],
+    ) -> BoxFuture<'a, Result<u64, String>> {
+        let deleted = self.with_users(scope, |users| {
+            ids.iter().filter(|id| soft_delete(users, **id)).count() as u64
+        });
+        future::ready(Ok(deleted)).boxed()
+    }
+
+    fn delete_all<'a>(&'a self, scope: &'a StoreScope) -> BoxFuture<'a, Result<u64, String>> {
+        let deleted = self.with_users(scope, |users| {
+            let deleted = users.len() as u64;
+            users.clear();
+            deleted
+        });
+        future::ready(Ok(deleted)).boxed()
+    }
+
+    fn ensure_indexes<'a>(
+        &'a self,
+        _scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<IndexesReport, String>> {
+        // Nothing to create, email uniqueness is checked on every write
+        future::ready(Ok(IndexesReport::default())).boxed()
+    }
+
+    fn list_indexes<'a>(
+        &'a self,
+        _scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<Vec<IndexInfo>, String>> {
+        // No indexes, see `ensure_indexes`
+        future::ready(Ok(Vec::new())).boxed()
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), String>> {
+        future::ready(Ok(())).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    fn scope() -> StoreScope {
+        StoreScope {
+            database: "test".into(),
+            comment: None,
+        }
+    }
+
+    fn user(first_name: &str, email: &str, age: Option<u8>) -> User {
+        User {
+            _id: ObjectId::new(),
+            first_name: first_name.into(),
+            last_name: "Lovelace".into(),
+            email: email.into(),
+            age,
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+        }
+    }
+
+    async fn seeded() -> MemoryUserStore {
+        let store = MemoryUserStore::new(false);
+        for user in [
+            user("Charles", "c@example.com", Some(40)),
+            user("Ada", "a@example.com", None),
+            user("Bob", "b@example.com", Some(17)),
+        ] {
+            store.insert(&scope(), user).await.unwrap();
+        }
+        store
+    }
+
+    #[actix_web::test]
+    async fn list_filters_sorts_and_pages() {
+        let store = seeded().await;
+        let sort = SortConfig {
+            field: "age".into(),
+            descending: false,
+        };
+
+        let names = |users: Vec<User>| -> Vec<String> {
+            users.into_iter().map(|user| user.first_name).collect()
+        };
+        let all = store
+            .list(
+                &scope(),
+                &UsersFilterQuery::default(),
+                &sort,
+                Page::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(names(all), ["Ada", "Bob", "Charles"]);
+
+        let page = Page {
+            skip: 1,
+            limit: Some(1),
+            ..Default::default()
+        };
+        let second = store
+            .list(&scope(), &UsersFilterQuery::default(), &sort, page)
+            .await
+            .unwrap();
+        assert_eq!(names(second), ["Bob"]);
+
+        let adults = UsersFilterQuery {
+            min_age: Some(18),
+            ..Default::default()
+        };
+        let found = store
+            .list(&scope(), &adults, &sort, Page::default())
+            .await
+            .unwrap();
+        assert_eq!(names(found), ["Charles"]);
+        assert_eq!(store.count(&scope(), &adults, None).await, Ok(1));
+    }
+
+    #[actix_web::test]
+    async fn text_search_ranks_by_matched_words() {
+        let store = seeded().await;
+        store
+            .insert(&scope(), user("Ada Mary", "m@example.com", None))
+            .await
+            .unwrap();
+
+        let found = store
+            .text_search(&scope(), "ada MARY", 10)
+            .await
+            .unwrap()
+            .unwrap();
+        let names: Vec<(&str, f64)> = found
+            .iter()
+            .map(|found| (found.user.first_name.as_str(), found.score))
+            .collect();
+        assert_eq!(names, [("Ada Mary", 2.0), ("Ada", 1.0)]);
+
+        let found = store.text_search(&scope(), "lovelace", 2).await.unwrap();
+        assert_eq!(found.map(|found| found.len()), Some(2));
+    }
+
+    #[actix_web::test]
+    async fn emails_are_unique() {
+        let store = seeded().await;
+        assert!(matches!(
+            store
+                .insert(&scope(), user("Eve", "a@example.com", None))
+                .await,
+            Err(AppError::Duplicate(_))
+        ));
+
+        let bob = store
+            .find_by_email(&scope(), "b@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        let res = store
+            .update_by_id(
+                &scope(),
+                bob._id,
+                doc! { "email": "a@example.com" },
+                DateTime::now(),
+            )
+            .await;
+        assert!(matches!(res, Err(AppError::Duplicate(_))));
+    }
+
+    #[actix_web::test]
+    async fn deleted_users_free_their_email_only_when_reuse_is_on() {
+        for reuse in [false, true] {
+            let store = MemoryUserStore::new(reuse);
+            let ada = user("Ada", "a@example.com", None);
+            let id = store.insert(&scope(), ada).await.unwrap();
+            store.delete_by_id(&scope(), id).await.unwrap();
+
+            let res = store
+                .insert(&scope(), user("Eve", "a@example.com", None))
+                .await;
+            assert_eq!(res.is_ok(), reuse, "reuse {}", reuse);
+        }
+    }
+
+    #[actix_web::test]
+    async fn updates_report_matched_and_modified() {
+        let store = seeded().await;
+        let set = doc! { "last_name": "Byron" };
+
+        let all = UsersFilterQuery::default();
+        let res = store.update_many(&scope(), &all, set.clone()).await;
+        assert_eq!(
+            res,
+            Ok(UpdateCounts {
+                matched: 3,
+                modified: 3
+            })
+        );
+        let res = store.update_many(&scope(), &all, set).await;
+        assert_eq!(
+            res,
+            Ok(UpdateCounts {
+                matched: 3,
+                modified: 0
+            })
+        );
+
+        let res = store
+            .update_by_id(
+                &scope(),
+                ObjectId::new(),
+                doc! { "age": 1 },
+                DateTime::now(),
+            )
+            .await;
+        assert!(matches!(res, Ok(None)));
+    }
+
+    #[actix_web::test]
+    async fn databases_are_isolated() {
+        let store = seeded().await;
+        let other = StoreScope {
+            database: "other".into(),
+            comment: None,
+        };
+        assert_eq!(store.estimated_count(&other).await, Ok(0));
+        assert_eq!(store.delete_all(&scope()).await, Ok(3));
+        assert_eq!(store.estimated_count(&scope()).await, Ok(0));
+    }
+}
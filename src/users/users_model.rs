@@ -1,13 +1,250 @@
-use mongodb::bson::oid::ObjectId;
-use serde::{Deserialize, Serialize};
+use mongodb::{
+    IndexModel,
+    bson::{Bson, DateTime, Document, doc, oid::ObjectId},
+    options::IndexOptions,
+};
+use serde::{Deserialize, Deserializer, Serialize};
 
-pub const USERS_COLLECTION: &str = "users";
-
-#[derive(Serialize, Deserialize, Debug)]
+/// User document.
+///
+/// # Schema evolution
+/// A document that fails to deserialize is skipped by list queries, so it becomes
+/// invisible. To keep existing records readable:
+/// - unknown fields are ignored, never add `deny_unknown_fields`
+/// - only `_id` and `email` are required, every other field has a serde default
+/// - fields added later must be `Option<T>` or carry `#[serde(default)]`
+/// - a stored value of the wrong type or out of range falls back to the default instead
+///   of failing the whole document (see `age`)
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     pub _id: ObjectId, // Option<ObjectId>,
+    #[serde(default)]
     pub first_name: String,
+    #[serde(default)]
     pub last_name: String,
     pub email: String,
+    #[serde(default, deserialize_with = "lenient_age")]
     pub age: Option<u8>,
+    /// Set on creation, absent on documents written before the field existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime>,
+    /// Set on creation, moved by every update that changes a value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime>,
+    /// See [`DELETED_AT_FIELD`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime>,
+}
+
+impl User {
+    /// Stored `created_at`, or the creation time embedded in `_id` for older documents.
+    pub fn created_at(&self) -> DateTime {
+        self.created_at.unwrap_or_else(|| self._id.timestamp())
+    }
+
+    /// Stored `updated_at`, or [`User::created_at`] for documents never updated since.
+    pub fn updated_at(&self) -> DateTime {
+        self.updated_at.unwrap_or_else(|| self.created_at())
+    }
+}
+
+/// Reads `age` as any BSON number, values that do not fit a `u8` (or non numbers) become `None`.
+fn lenient_age<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    let value = Option::<Bson>::deserialize(deserializer)?;
+    Ok(match value {
+        Some(Bson::Int32(v)) => u8::try_from(v).ok(),
+        Some(Bson::Int64(v)) => u8::try_from(v).ok(),
+        Some(Bson::Double(v)) if v.fract() == 0.0 && (0.0..=255.0).contains(&v) => Some(v as u8),
+        _ => None,
+    })
+}
+
+/// User with the fields computed by the profile aggregation (see `users_repository::find_profile`).
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserProfile {
+    #[serde(flatten)]
+    pub user: User,
+    pub full_name: String,
+    pub age_group: String,
+    /// Stored `created_at`, or the creation time embedded in `_id` for older documents.
+    pub created_at: DateTime,
+    pub account_age_days: i64,
+}
+
+/// User matched by a text search, with its relevance (MongoDB `textScore`, higher is better).
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScoredUser {
+    #[serde(flatten)]
+    pub user: User,
+    pub score: f64,
+}
+
+/// Fields no update request may modify.
+pub const IMMUTABLE_FIELDS: [&str; 1] = ["_id"];
+
+/// Outcome of an `ensure_indexes` run, listed by index name.
+#[derive(Serialize, Debug, Default)]
+pub struct IndexesReport {
+    pub created: Vec<String>,
+    pub existing: Vec<String>,
+}
+
+/// An index of the users collection, as listed by `GET /users/_indexes`.
+#[derive(Serialize, Debug)]
+pub struct IndexInfo {
+    pub name: String,
+    /// Key specification, e.g. `{ "email": 1 }`.
+    pub key: Document,
+    pub unique: bool,
+}
+
+impl From<IndexModel> for IndexInfo {
+    fn from(index: IndexModel) -> Self {
+        let options = index.options.unwrap_or_default();
+        IndexInfo {
+            name: options.name.unwrap_or_default(),
+            key: index.keys,
+            unique: options.unique.unwrap_or(false),
+        }
+    }
+}
+
+/// Deletion timestamp of a soft-deleted user, absent on active users.
+///
+/// Soft-deleted users stay in the collection for auditing, every query but the admin
+/// ones filters them out (see `users_repository::active`).
+pub const DELETED_AT_FIELD: &str = "deleted_at";
+
+/// Unique email across all users, deleted ones included.
+pub const EMAIL_UNIQUE_INDEX: &str = "email_unique";
+
+/// Unique email among active users, see [`users_indexes`].
+pub const EMAIL_UNIQUE_ACTIVE_INDEX: &str = "email_unique_active";
+
+/// Text index on the names, required by `$text` searches (a collection has at most one).
+pub const NAME_TEXT_INDEX: &str = "name_text";
+
+/// Declarative index registry for the users collection.
+///
+/// Every index must be named: `ensure_indexes` compares these names with the
+/// indexes already present and only creates the missing ones.
+///
+/// With `email_reuse_after_delete` the email of a deleted user may be taken again. A
+/// partial index can't express it: `partialFilterExpression` does not support
+/// `{ deleted_at: { $exists: false } }`. The unique key is `(email, deleted_at)` instead,
+/// active users all index `deleted_at` as null so their emails stay unique, while each
+/// deleted user carries its own timestamp.
+pub fn users_indexes(email_reuse_after_delete: bool) -> Vec<IndexModel> {
+    let email_unique = if email_reuse_after_delete {
+        IndexModel::builder()
+            .keys(doc! { "email": 1, DELETED_AT_FIELD: 1 })
+            .options(
+                IndexOptions::builder()
+                    .name(EMAIL_UNIQUE_ACTIVE_INDEX.to_string())
+                    .unique(true)
+                    .build(),
+            )
+            .build()
+    } else {
+        IndexModel::builder()
+            .keys(doc! { "email": 1 })
+            .options(
+                IndexOptions::builder()
+                    .name(EMAIL_UNIQUE_INDEX.to_string())
+                    .unique(true)
+                    .build(),
+            )
+            .build()
+    };
+
+    // Names are not words of a language: no stemming, no stop words
+    let name_text = IndexModel::builder()
+        .keys(doc! { "first_name": "text", "last_name": "text" })
+        .options(
+            IndexOptions::builder()
+                .name(NAME_TEXT_INDEX.to_string())
+                .default_language("none".to_string())
+                .build(),
+        )
+        .build();
+
+    vec![email_unique, name_text]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::from_document;
+
+    #[test]
+    fn index_models_keep_name_keys_and_uniqueness() {
+        let indexes: Vec<IndexInfo> = users_indexes(false)
+            .into_iter()
+            .map(IndexInfo::from)
+            .collect();
+        assert_eq!(indexes[0].name, EMAIL_UNIQUE_INDEX);
+        assert_eq!(indexes[0].key, doc! { "email": 1 });
+        assert!(indexes[0].unique);
+        assert_eq!(indexes[1].name, NAME_TEXT_INDEX);
+        assert!(!indexes[1].unique);
+    }
+
+    #[test]
+    fn oldest_documents_only_need_id_and_email() {
+        let id = ObjectId::new();
+        let user: User = from_document(doc! { "_id": id, "email": "a@b.c" }).unwrap();
+        assert_eq!(user.first_name, "");
+        assert_eq!(user.last_name, "");
+        assert_eq!(user.age, None);
+        assert_eq!(user._id, id);
+        assert_eq!(user.created_at(), id.timestamp());
+        assert_eq!(user.updated_at(), id.timestamp());
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let user: User = from_document(doc! {
+            "_id": ObjectId::new(),
+            "email": "a@b.c",
+            "nickname": "ada",
+            "address": { "city": "London" },
+        })
+        .unwrap();
+        assert_eq!(user.email, "a@b.c");
+    }
+
+    #[test]
+    fn unusable_age_falls_back_to_none() {
+        for age in [
+            Bson::Int32(-1),
+            Bson::Int64(300),
+            Bson::Double(3.5),
+            Bson::from("36"),
+        ] {
+            let user: User =
+                from_document(doc! { "_id": ObjectId::new(), "email": "a@b.c", "age": age })
+                    .unwrap();
+            assert_eq!(user.age, None);
+        }
+        let user: User =
+            from_document(doc! { "_id": ObjectId::new(), "email": "a@b.c", "age": 36.0 }).unwrap();
+        assert_eq!(user.age, Some(36));
+    }
+
+    #[test]
+    fn missing_email_is_still_an_error() {
+        assert!(from_document::<User>(doc! { "_id": ObjectId::new() }).is_err());
+    }
+
+    #[test]
+    fn email_reuse_keys_the_unique_index_on_deleted_at() {
+        let index = &users_indexes(true)[0];
+        assert_eq!(index.keys, doc! { "email": 1, DELETED_AT_FIELD: 1 });
+        let options = index.options.as_ref().unwrap();
+        assert_eq!(options.name.as_deref(), Some(EMAIL_UNIQUE_ACTIVE_INDEX));
+        assert_eq!(options.unique, Some(true));
+
+        let index = &users_indexes(false)[0];
+        assert_eq!(index.keys, doc! { "email": 1 });
+    }
 }
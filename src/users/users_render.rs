@@ -0,0 +1,64 @@
+use actix_web::{HttpRequest, HttpResponse, get, http::StatusCode, middleware::from_fn, web};
+use handlebars::Handlebars;
+use log::error;
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    shared::{
+        config::settings::Settings,
+        dto::response::{
+            ErrorCode, http_bad_request, http_error, http_internal_server_error, http_render,
+        },
+        middleware::timeout::timeout,
+    },
+    users::{
+        dto::UserDtoResponse,
+        users_service,
+        users_store::{StoreScope, UserStore},
+    },
+};
+
+/// Serves the user page by rendering the `user.hbs` template.
+///
+/// Names and email are user supplied: the template only uses escaped `{{expr}}`
+/// output, so markup such as `<script>` in a name is rendered as text.
+///
+/// # Route
+/// `GET /users/{id}/view` - User page
+#[get("{id}/view", wrap = "from_fn(timeout)")]
+async fn view(
+    req: HttpRequest,
+    hb: web::Data<Handlebars<'_>>,
+    store: web::Data<dyn UserStore>,
+    config: web::Data<Settings>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let Ok(object_id) = ObjectId::parse_str(&id) else {
+        return http_bad_request(format!("Invalid user id {}", id));
+    };
+
+    let scope = StoreScope {
+        database: config.database_for(&req),
+        comment: None,
+    };
+    match users_service::find_by_id(&store, &scope, object_id, None).await {
+        Ok(Some(user)) => http_render(&hb, "user", &UserDtoResponse::from(user), config.dev_mode),
+        Ok(None) => http_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::UserNotFound,
+            format!("User not found for id {}", id),
+        ),
+        Err(err) => {
+            error!("{}", err);
+            http_internal_server_error(format!("Generic error finding id {}", id))
+        }
+    }
+}
+
+/// Service configuration for user page routes.
+///
+/// Registers all user page handlers with the Actix-web application.
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(view);
+}
@@ -1,40 +1,1108 @@
 //! Data access layer for user operations.
 //!
 //! This repository layer handles all database operations for the User entity.
-use actix_web::web;
-
+use futures_util::{
+    future::{self, BoxFuture, FutureExt},
+    stream::{self, BoxStream, StreamExt},
+};
 use mongodb::{
-    Client, Collection,
-    bson::{doc, oid::ObjectId},
+    Client, ClientSession, Collection,
+    bson::{Bson, DateTime, Document, doc, from_document, oid::ObjectId, to_document},
+    error::{ErrorKind, InsertManyError, TRANSIENT_TRANSACTION_ERROR, WriteFailure},
+    options::{
+        AggregateOptions, CountOptions, DeleteOptions, DistinctOptions,
+        EstimatedDocumentCountOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions,
+        InsertManyOptions, InsertOneOptions, ReturnDocument, UpdateOptions,
+    },
+    results::UpdateResult,
 };
 
 use crate::{
-    shared::config::settings::DATABASE_NAME,
-    users::users_model::{USERS_COLLECTION, User},
+    shared::{concurrency::db_limiter::db_permit, error::AppError},
+    users::{
+        dto::UsersFilterQuery,
+        users_model::{
+            DELETED_AT_FIELD, EMAIL_UNIQUE_ACTIVE_INDEX, EMAIL_UNIQUE_INDEX, IndexInfo,
+            IndexesReport, ScoredUser, User, UserProfile, users_indexes,
+        },
+        users_store::{InsertFailure, Page},
+    },
 };
-use log::error;
+use log::{error, info, warn};
+
+/// MongoDB error code returned when the collection does not exist yet.
+const NAMESPACE_NOT_FOUND: i32 = 26;
+
+/// MongoDB error code returned by `$text` queries when the collection has no text index.
+const INDEX_NOT_FOUND: i32 = 27;
+
+/// MongoDB `DuplicateKey` error code, raised by unique indexes.
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Runs of a transaction aborted by a transient error (e.g. a write conflict).
+const TRANSACTION_ATTEMPTS: u32 = 3;
+
+/// Returns the users collection `name` of the given database.
+///
+/// Database and collection names come from `Settings`, never from a module constant,
+/// so the same binary can target differently named collections.
+pub fn collection(client: &Client, database: &str, name: &str) -> Collection<User> {
+    client.database(database).collection(name)
+}
+
+/// Restricts `filter` to active users, `null` also matches a missing `deleted_at`.
+pub fn active(mut filter: Document) -> Document {
+    filter.insert(DELETED_AT_FIELD, Bson::Null);
+    filter
+}
+
+/// MongoDB operation `comment` carrying the request id, `None` when disabled.
+///
+/// The comment shows up in the database profiler and slow query logs, linking a slow
+/// query to the HTTP request that issued it.
+pub fn query_comment(enabled: bool, request_id: Option<String>) -> Option<Bson> {
+    if enabled {
+        request_id.map(Bson::String)
+    } else {
+        None
+    }
+}
+
+/// Checks the server answers, used by readiness probes.
+///
+/// Runs outside the `db_permit` limiter: a saturated pool is busy, not down.
+pub async fn ping(client: &Client) -> Result<(), String> {
+    match client
+        .database("admin")
+        .run_command(doc! { "ping": 1 })
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("{}", err);
+            Err("MongoDB ping failed".into())
+        }
+    }
+}
+
+/// Users matching `filter` in `sort` order, sliced by `page`.
+///
+/// With `page.after` only the users past that id are read, in `_id` order: an index range
+/// scan, as fast on the last page as on the first.
+///
+/// Documents that do not map to `User` are logged and skipped.
+pub async fn find_all(
+    collection: &Collection<User>,
+    filter: Document,
+    sort: Document,
+    page: Page,
+    projection: Option<Document>,
+    comment: Option<Bson>,
+) -> Result<Vec<User>, String> {
+    // A cursor page resumes after its id, which needs the `_id` order
+    let (filter, sort) = match page.after {
+        Some(after) => (
+            doc! { "$and": [filter, { "_id": { "$gt": after } }] },
+            doc! { "_id": 1 },
+        ),
+        None => (filter, sort),
+    };
+    // Held until the cursor is exhausted, getMore round trips are operations too
+    let _permit = db_permit().await;
+    // Fetch from the network with batch size of 100 elements per network call
+    let find_opts: FindOptions = FindOptions::builder()
+        .batch_size(100)
+        .sort(sort)
+        .skip((page.skip > 0).then_some(page.skip))
+        .limit(page.limit.and_then(|limit| i64::try_from(limit).ok()))
+        .projection(projection)
+        .comment(comment)
+        .build();
+    let mut cursor = match collection.find(filter).with_options(find_opts).await {
+        Ok(cursor) => cursor,
+        Err(err) => {
+            error!("Error running find: {}", err);
+            return Err("Database query error".into());
+        }
+    };
+
+    let mut users = Vec::new();
+    // Retrieve and deserialize user data from cursor
+    while cursor.advance().await.unwrap_or(false) {
+        match cursor.deserialize_current() {
+            Ok(user) => users.push(user),
+            Err(err) => error!("Not valid user; {}", err),
+        }
+    }
+
+    // Alternative approach using futures (commented out)
+    // while let Some(result) = cursor.try_next().await.unwrap_or_else(|err| {
+    //     error!("Not valid user: {}", err);
+    //     None
+    // }) {
+    //     users.push(result);
+    // }
+
+    Ok(users)
+}
+
+/// Documents `stream_all` reads per permit, also its cursor `batch_size`: a permit covers
+/// at most one `getMore`.
+const STREAM_BATCH_SIZE: usize = 100;
+
+/// Streams every active user ordered by `_id`, for exports too large to collect.
+///
+/// A permit is taken per batch of [`STREAM_BATCH_SIZE`] documents and released before the
+/// batch is yielded, so a slow client downloading an export does not hold one while the
+/// body is sent. Invalid documents are logged and skipped, a failing cursor yields one
+/// error and ends the stream.
+pub fn stream_all(
+    collection: Collection<User>,
+    comment: Option<Bson>,
+) -> BoxStream<'static, Result<User, String>> {
+    async move {
+        let find_opts: FindOptions = FindOptions::builder()
+            .batch_size(STREAM_BATCH_SIZE as u32)
+            .sort(doc! { "_id": 1 })
+            .comment(comment)
+            .build();
+        let found = {
+            let _permit = db_permit().await;
+            collection
+                .find(active(doc! {}))
+                .with_options(find_opts)
+                .await
+        };
+        let cursor = match found {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                error!("Error running find: {}", err);
+                return stream::once(future::ready(Err("Database query error".into()))).boxed();
+            }
+        };
+
+        stream::unfold(Some(cursor), |state| async move {
+            let mut cursor = state?;
+            let _permit = db_permit().await;
+            let mut batch = Vec::with_capacity(STREAM_BATCH_SIZE);
+            while batch.len() < STREAM_BATCH_SIZE {
+                match cursor.advance().await {
+                    Ok(true) => match cursor.deserialize_current() {
+                        Ok(user) => batch.push(Ok(user)),
+                        Err(err) => error!("Not valid user; {}", err),
+                    },
+                    Ok(false) => return (!batch.is_empty()).then_some((batch, None)),
+                    Err(err) => {
+                        error!("Error reading cursor: {}", err);
+                        batch.push(Err("Database query error".into()));
+                        return Some((batch, None));
+                    }
+                }
+            }
+            Some((batch, Some(cursor)))
+        })
+        .flat_map(stream::iter)
+        .boxed()
+    }
+    .flatten_stream()
+    .boxed()
+}
+
+/// `$text` search on the names, best matches first (`textScore`, then `_id`).
+///
+/// Returns `None` when the collection has no text index (see `ensure_indexes`).
+pub async fn text_search(
+    collection: &Collection<User>,
+    query: &str,
+    limit: u64,
+    comment: Option<Bson>,
+) -> Result<Option<Vec<ScoredUser>>, String> {
+    let _permit = db_permit().await;
+    let score = doc! { "$meta": "textScore" };
+    let find_opts: FindOptions = FindOptions::builder()
+        .projection(doc! { "score": score.clone() })
+        .sort(doc! { "score": score, "_id": 1 })
+        .limit(i64::try_from(limit).ok())
+        .comment(comment)
+        .build();
+    let mut cursor = match collection
+        .clone_with_type::<ScoredUser>()
+        .find(active(doc! { "$text": { "$search": query } }))
+        .with_options(find_opts)
+        .await
+    {
+        Ok(cursor) => cursor,
+        Err(err) => match err.kind.as_ref() {
+            ErrorKind::Command(cmd) if cmd.code == INDEX_NOT_FOUND => return Ok(None),
+            _ => {
+                error!("Error running text search: {}", err);
+                return Err("Database query error".into());
+            }
+        },
+    };
+
+    let mut users = Vec::new();
+    while cursor.advance().await.unwrap_or(false) {
+        match cursor.deserialize_current() {
+            Ok(user) => users.push(user),
+            Err(err) => error!("Not valid user; {}", err),
+        }
+    }
+
+    Ok(Some(users))
+}
+
+/// Finds an active user by its (already normalized) email.
+pub async fn find_by_email(
+    collection: &Collection<User>,
+    email: &str,
+    comment: Option<Bson>,
+) -> Result<Option<User>, String> {
+    let _permit = db_permit().await;
+    collection
+        .find_one(active(doc! { "email": email }))
+        .with_options(FindOneOptions::builder().comment(comment).build())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// True for the duplicate-key error of a unique index, reported as a write error by
+/// inserts and as a command error by `findAndModify`.
+fn is_duplicate_key(err: &mongodb::error::Error) -> bool {
+    match err.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(write)) => write.code == DUPLICATE_KEY,
+        ErrorKind::Command(cmd) => cmd.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+/// Runs `f` in a transaction of a new session of `client`, committed when `f` succeeds and
+/// aborted when it fails.
+///
+/// Every operation of `f` must pass the session (`.session(&mut *session)`), the others run
+/// outside the transaction. `f` runs again, up to `TRANSACTION_ATTEMPTS` times, when MongoDB
+/// labels the failure transient, so it must not have effects outside MongoDB. `f` gets a
+/// fresh borrow of the session on each run, it moves owned clones of what it needs into the
+/// returned future.
+///
+/// Transactions require a replica set or a sharded cluster, on a standalone server they
+/// fail before `f` runs (`MONGODB_TRANSACTIONS` is checked at startup, see `init_mongodb`).
+pub async fn with_transaction<T, F>(client: &Client, mut f: F) -> Result<T, AppError>
+where
+    F: for<'s> FnMut(&'s mut ClientSession) -> BoxFuture<'s, Result<T, AppError>>,
+{
+    let mut session = client.start_session().await?;
+    let mut attempt = 1;
+    loop {
+        if let Err(err) = session.start_transaction().await {
+            error!(
+                "Cannot start a MongoDB transaction, they need a replica set or a sharded cluster: {}",
+                err
+            );
+            return Err(err.into());
+        }
+        let res = match f(&mut session).await {
+            Ok(value) => match session.commit_transaction().await {
+                Ok(()) => return Ok(value),
+                Err(err) => AppError::from(err),
+            },
+            Err(err) => {
+                // the server may have aborted it already, it expires anyway
+                if let Err(abort) = session.abort_transaction().await {
+                    warn!("Failed to abort MongoDB transaction: {}", abort);
+                }
+                err
+            }
+        };
+        match res {
+            AppError::Database(err)
+                if err.contains_label(TRANSIENT_TRANSACTION_ERROR)
+                    && attempt < TRANSACTION_ATTEMPTS =>
+            {
+                warn!(
+                    "Transient MongoDB transaction error (attempt {}/{}), retrying: {}",
+                    attempt, TRANSACTION_ATTEMPTS, err
+                );
+                attempt += 1;
+            }
+            err => return Err(err),
+        }
+    }
+}
 
-// NOTE: The following repository methods are currently implemented directly in the controller.
-// Uncomment and implement these methods to follow the complete repository pattern:
-// pub async fn get_all() {}
-// pub async fn get_by_id() {}
-// pub async fn create() {}
-// pub async fn update_by_id() {}
+/// `insert` after the active-email check, both in one transaction (`MONGODB_TRANSACTIONS`).
+///
+/// The check and the insert see the same snapshot and a failed insert leaves nothing behind.
+/// Concurrent creations insert different documents, which never conflict: the unique email
+/// index still decides between them. `create` skips its own `find_by_email` pre-check in
+/// this mode, the check here replaces it.
+pub async fn insert_in_transaction(
+    client: &Client,
+    collection: &Collection<User>,
+    user: User,
+    comment: Option<Bson>,
+) -> Result<ObjectId, AppError> {
+    let _permit = db_permit().await;
+    with_transaction(client, |session| {
+        let (collection, user, comment) = (collection.clone(), user.clone(), comment.clone());
+        async move {
+            let email = user.email.clone();
+            let existing = collection
+                .find_one(active(doc! { "email": &email }))
+                .with_options(FindOneOptions::builder().comment(comment.clone()).build())
+                .session(&mut *session)
+                .await?;
+            if existing.is_some() {
+                return Err(AppError::Duplicate(format!(
+                    "Email {} already exists",
+                    email
+                )));
+            }
+            let id = user._id;
+            match collection
+                .insert_one(user)
+                .with_options(InsertOneOptions::builder().comment(comment).build())
+                .session(&mut *session)
+                .await
+            {
+                Ok(_) => Ok(id),
+                Err(err) if is_duplicate_key(&err) => Err(AppError::Duplicate(format!(
+                    "Email {} already exists",
+                    email
+                ))),
+                Err(err) => Err(err.into()),
+            }
+        }
+        .boxed()
+    })
+    .await
+}
 
-pub async fn delete_by_id(client: web::Data<Client>, id: &str) -> Result<(), String> {
-    let object_id = ObjectId::parse_str(id).unwrap_or_default();
-    let collection: Collection<User> = client.database(DATABASE_NAME).collection(USERS_COLLECTION);
+/// Inserts `user`, returns its id.
+///
+/// A duplicate email (unique email index) fails with [`AppError::Duplicate`], it is the
+/// last line of defence when concurrent creations pass the `find_by_email` pre-check.
+pub async fn insert(
+    collection: &Collection<User>,
+    user: User,
+    comment: Option<Bson>,
+) -> Result<ObjectId, AppError> {
+    let _permit = db_permit().await;
+    // the id is set by the caller, it is the inserted one
+    let (id, email) = (user._id, user.email.clone());
+    match collection
+        .insert_one(user)
+        .with_options(InsertOneOptions::builder().comment(comment).build())
+        .await
+    {
+        Ok(_) => Ok(id),
+        Err(err) if is_duplicate_key(&err) => Err(AppError::Duplicate(format!(
+            "Email {} already exists",
+            email
+        ))),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Replaces the whole document of `user._id` with `user`, returns the stored user or `None`
+/// when no active user has that id. The stored `created_at` is kept. Fails with
+/// [`AppError::Duplicate`] when the email belongs to another user.
+pub async fn replace_by_id(
+    collection: &Collection<User>,
+    user: User,
+    comment: Option<Bson>,
+) -> Result<Option<User>, AppError> {
+    let _permit = db_permit().await;
+    let (id, email) = (user._id, user.email.clone());
+    let replacement = to_document(&user).map_err(mongodb::error::Error::from)?;
+    // a missing `created_at` stays missing, `$literal` keeps `$` prefixed values as data
+    let pipeline = vec![doc! {
+        "$replaceWith": {
+            "$mergeObjects": [{ "created_at": "$created_at" }, { "$literal": replacement }]
+        }
+    }];
+    match collection
+        .find_one_and_update(active(doc! { "_id": id }), pipeline)
+        .with_options(
+            FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::After)
+                .comment(comment)
+                .build(),
+        )
+        .await
+    {
+        Ok(user) => Ok(user),
+        Err(err) if is_duplicate_key(&err) => Err(AppError::Duplicate(format!(
+            "Email {} already exists",
+            email
+        ))),
+        Err(err) => Err(err.into()),
+    }
+}
 
+/// Inserts `users` unordered: every user is attempted even when others fail.
+///
+/// Returns the position in `users` and the message of each user not inserted, a duplicate
+/// email answers `Already exists`.
+pub async fn insert_many(
+    collection: &Collection<User>,
+    users: Vec<User>,
+    comment: Option<Bson>,
+) -> Result<Vec<InsertFailure>, AppError> {
+    if users.is_empty() {
+        return Ok(Vec::new());
+    }
+    let _permit = db_permit().await;
     match collection
+        .insert_many(users)
+        .with_options(
+            InsertManyOptions::builder()
+                .ordered(false)
+                .comment(comment)
+                .build(),
+        )
+        .await
+    {
+        Ok(_) => Ok(Vec::new()),
+        Err(err) => match err.kind.as_ref() {
+            ErrorKind::InsertMany(InsertManyError {
+                write_errors: Some(write_errors),
+                ..
+            }) => Ok(write_errors
+                .iter()
+                .map(|write| {
+                    let duplicate = write.code == DUPLICATE_KEY;
+                    if !duplicate {
+                        error!("Error inserting user #{}: {}", write.index, write.message);
+                    }
+                    InsertFailure {
+                        index: write.index,
+                        duplicate,
+                    }
+                })
+                .collect()),
+            _ => Err(err.into()),
+        },
+    }
+}
+
+/// Soft-deletes the user `id` (sets `deleted_at`), fails with [`AppError::NotFound`] when
+/// no active user has that id.
+pub async fn delete_by_id(
+    collection: &Collection<User>,
+    id: ObjectId,
+    comment: Option<Bson>,
+) -> Result<(), AppError> {
+    let _permit = db_permit().await;
+
+    let res = collection
+        .update_one(
+            active(doc! { "_id": id }),
+            doc! { "$set": { DELETED_AT_FIELD: DateTime::now() } },
+        )
+        .with_options(UpdateOptions::builder().comment(comment).build())
+        .await?;
+    if res.matched_count == 0 {
+        return Err(AppError::NotFound(format!("User not found for id {}", id)));
+    }
+    Ok(())
+}
+
+/// Removes the document of the user `id`, soft-deleted or not, fails with
+/// [`AppError::NotFound`] when it does not exist.
+pub async fn hard_delete_by_id(
+    collection: &Collection<User>,
+    id: ObjectId,
+    comment: Option<Bson>,
+) -> Result<(), AppError> {
+    let _permit = db_permit().await;
+
+    let res = collection
         .delete_one(doc! {
-            "_id": object_id
+            "_id": id
         })
+        .with_options(DeleteOptions::builder().comment(comment).build())
+        .await?;
+    if res.deleted_count == 0 {
+        return Err(AppError::NotFound(format!("User not found for id {}", id)));
+    }
+    Ok(())
+}
+
+pub async fn find_by_id(
+    collection: &Collection<User>,
+    id: ObjectId,
+    comment: Option<Bson>,
+) -> Result<Option<User>, String> {
+    let _permit = db_permit().await;
+    collection
+        .find_one(active(doc! { "_id": id }))
+        .with_options(FindOneOptions::builder().comment(comment).build())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Finds a user document by id without mapping it to `User`.
+///
+/// Returns every stored field, including the ones the model ignores or cannot decode.
+/// Soft-deleted users are found too.
+pub async fn find_raw_by_id(
+    collection: &Collection<User>,
+    id: ObjectId,
+) -> Result<Option<Document>, String> {
+    let _permit = db_permit().await;
+    collection
+        .clone_with_type::<Document>()
+        .find_one(doc! { "_id": id })
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Finds a user by id with computed fields, derived by the database in one aggregation.
+///
+/// - `full_name`: first and last name joined by a space
+/// - `age_group`: bucket of `age`, `unknown` when missing
+/// - `created_at`: stored value, falls back to the `_id` timestamp
+/// - `account_age_days`: whole days since `created_at` (requires MongoDB 5.0+)
+pub async fn find_profile(
+    collection: &Collection<User>,
+    id: ObjectId,
+    comment: Option<Bson>,
+) -> Result<Option<UserProfile>, String> {
+    let _permit = db_permit().await;
+    let pipeline = vec![
+        doc! { "$match": active(doc! { "_id": id }) },
+        doc! { "$addFields": {
+            "created_at": { "$ifNull": ["$created_at", { "$toDate": "$_id" }] },
+        } },
+        doc! { "$addFields": {
+            "full_name": { "$trim": { "input": { "$concat": [
+                { "$ifNull": ["$first_name", ""] }, " ", { "$ifNull": ["$last_name", ""] },
+            ] } } },
+            "age_group": { "$switch": {
+                "branches": [
+                    { "case": { "$not": [{ "$isNumber": "$age" }] }, "then": "unknown" },
+                    { "case": { "$lt": ["$age", 18] }, "then": "minor" },
+                    { "case": { "$lt": ["$age", 30] }, "then": "18-29" },
+                    { "case": { "$lt": ["$age", 50] }, "then": "30-49" },
+                    { "case": { "$lt": ["$age", 65] }, "then": "50-64" },
+                ],
+                "default": "65+",
+            } },
+            "account_age_days": { "$dateDiff": {
+                "startDate": "$created_at", "endDate": "$$NOW", "unit": "day",
+            } },
+        } },
+    ];
+
+    let mut cursor = collection
+        .aggregate(pipeline)
+        .with_options(AggregateOptions::builder().comment(comment).build())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    match cursor.advance().await {
+        Ok(true) => cursor
+            .deserialize_current()
+            .map_err(|err| err.to_string())
+            .and_then(|document| {
+                from_document::<UserProfile>(document).map_err(|err| err.to_string())
+            })
+            .map(Some),
+        Ok(false) => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Returns one random active user using a `$sample` aggregation stage, `None` when empty.
+pub async fn find_random(
+    collection: &Collection<User>,
+    comment: Option<Bson>,
+) -> Result<Option<User>, String> {
+    let _permit = db_permit().await;
+    let mut cursor = match collection
+        .aggregate(vec![
+            doc! { "$match": active(doc! {}) },
+            doc! { "$sample": { "size": 1 } },
+        ])
+        .with_options(AggregateOptions::builder().comment(comment).build())
         .await
     {
-        Ok(_) => Ok(()),
+        Ok(cursor) => cursor,
         Err(err) => {
             error!("{}", err);
-            Err(format!("Delete failed for id {}", id))
+            return Err("Random user lookup failed".into());
+        }
+    };
+
+    match cursor.advance().await {
+        Ok(true) => cursor
+            .deserialize_current()
+            .map_err(|err| err.to_string())
+            .and_then(|document| from_document::<User>(document).map_err(|err| err.to_string()))
+            .map(Some)
+            .map_err(|err| {
+                error!("Not valid user; {}", err);
+                "Random user lookup failed".into()
+            }),
+        Ok(false) => Ok(None),
+        Err(err) => {
+            error!("{}", err);
+            Err("Random user lookup failed".into())
+        }
+    }
+}
+
+/// Returns which of the given ids belong to an active user.
+pub async fn find_existing_ids(
+    collection: &Collection<User>,
+    ids: &[ObjectId],
+    comment: Option<Bson>,
+) -> Result<Vec<ObjectId>, String> {
+    let _permit = db_permit().await;
+    match collection
+        .distinct("_id", active(doc! { "_id": { "$in": ids.to_vec() } }))
+        .with_options(DistinctOptions::builder().comment(comment).build())
+        .await
+    {
+        Ok(values) => Ok(values
+            .into_iter()
+            .filter_map(|v| match v {
+                Bson::ObjectId(oid) => Some(oid),
+                _ => None,
+            })
+            .collect()),
+        Err(err) => {
+            error!("{}", err);
+            Err("Lookup of user ids failed".into())
+        }
+    }
+}
+
+/// Soft-deletes every active user whose id is listed, returns the number of deleted users.
+pub async fn delete_many_by_ids(
+    collection: &Collection<User>,
+    ids: &[ObjectId],
+    comment: Option<Bson>,
+) -> Result<u64, String> {
+    let _permit = db_permit().await;
+    match collection
+        .update_many(
+            active(doc! { "_id": { "$in": ids.to_vec() } }),
+            doc! { "$set": { DELETED_AT_FIELD: DateTime::now() } },
+        )
+        .with_options(UpdateOptions::builder().comment(comment).build())
+        .await
+    {
+        Ok(res) => Ok(res.modified_count),
+        Err(err) => {
+            error!("{}", err);
+            Err("Bulk delete failed".into())
+        }
+    }
+}
+
+/// Builds the MongoDB filter matching every field set in `query`.
+///
+/// An empty query matches every active user, or the whole collection with
+/// `include_deleted`.
+pub fn build_filter(query: &UsersFilterQuery) -> Document {
+    let mut filter = Document::new();
+    if let Some(first_name) = &query.first_name {
+        filter.insert("first_name", first_name);
+    }
+    if let Some(last_name) = &query.last_name {
+        filter.insert("last_name", last_name);
+    }
+    if let Some(email) = &query.email {
+        filter.insert("email", email);
+    }
+
+    let mut age = Document::new();
+    if let Some(value) = query.age {
+        age.insert("$eq", i32::from(value));
+    }
+    if let Some(min) = query.min_age {
+        age.insert("$gte", i32::from(min));
+    }
+    if let Some(max) = query.max_age {
+        age.insert("$lte", i32::from(max));
+    }
+    if !age.is_empty() {
+        filter.insert("age", age);
+    }
+
+    if query.include_deleted {
+        filter
+    } else {
+        active(filter)
+    }
+}
+
+/// Projection of the `fields` selection of `query`, `None` to read whole documents.
+///
+/// `_id` and `email`, required to read a `User`, are always included, the handler drops
+/// what was not requested. `updated_at` falls back to `created_at`, fetched with it.
+pub fn build_projection(query: &UsersFilterQuery) -> Option<Document> {
+    let fields = query.fields().ok()??;
+    let mut projection = doc! { "_id": 1, "email": 1 };
+    for field in fields {
+        match field {
+            "id" | "email" => {}
+            "updated_at" => {
+                projection.insert("updated_at", 1);
+                projection.insert("created_at", 1);
+            }
+            field => {
+                projection.insert(field, 1);
+            }
+        }
+    }
+    Some(projection)
+}
+
+/// Filter of `UserStore::search`: active users with `term` in a name or the email.
+///
+/// `term` is escaped, so it is matched literally and can't inject an expensive pattern.
+pub fn build_search_filter(term: &str) -> Document {
+    let pattern = regex::escape(term);
+    let contains = |field: &str| doc! { field: { "$regex": &pattern, "$options": "i" } };
+    active(doc! {
+        "$or": [contains("first_name"), contains("last_name"), contains("email")]
+    })
+}
+
+/// Collection size from its metadata, soft-deleted users included. O(1) but may be
+/// slightly off (e.g. after an unclean shutdown or during orphaned chunk migrations on
+/// sharded clusters).
+pub async fn estimated_count(
+    collection: &Collection<User>,
+    comment: Option<Bson>,
+) -> Result<u64, String> {
+    let _permit = db_permit().await;
+    match collection
+        .estimated_document_count()
+        .with_options(
+            EstimatedDocumentCountOptions::builder()
+                .comment(comment)
+                .build(),
+        )
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => {
+            error!("{}", err);
+            Err("Count failed".into())
+        }
+    }
+}
+
+/// Exact number of users matching `filter`, scanning at most `limit` documents when set.
+pub async fn count(
+    collection: &Collection<User>,
+    filter: Document,
+    limit: Option<u64>,
+    comment: Option<Bson>,
+) -> Result<u64, String> {
+    let _permit = db_permit().await;
+    match collection
+        .count_documents(filter)
+        .with_options(
+            CountOptions::builder()
+                .limit(limit)
+                .comment(comment)
+                .build(),
+        )
+        .await
+    {
+        Ok(count) => Ok(count),
+        Err(err) => {
+            error!("{}", err);
+            Err("Count failed".into())
+        }
+    }
+}
+
+/// Update pipeline applying `set` like a `$set`, `updated_at` only becomes `now` (a date, or
+/// `"$$NOW"` for the server clock) when one of the values differs so that no-op updates
+/// keep a `modified_count` of 0.
+///
+/// Values are wrapped in `$literal`: in a pipeline a string starting with `$` would
+/// otherwise be read as a field path.
+fn set_pipeline(set: Document, now: Bson) -> Vec<Document> {
+    let changed: Vec<Bson> = set
+        .iter()
+        .map(|(field, value)| {
+            Bson::Document(doc! { "$ne": [format!("${}", field), { "$literal": value.clone() }] })
+        })
+        .collect();
+    let values: Document = set
+        .into_iter()
+        .map(|(field, value)| (field, Bson::Document(doc! { "$literal": value })))
+        .collect();
+    vec![
+        doc! {
+            "$set": {
+                "updated_at": { "$cond": [{ "$or": changed }, now, "$updated_at"] }
+            }
+        },
+        doc! { "$set": values },
+    ]
+}
+
+/// Applies `set` to the active user `id` in one `findAndModify`, `updated_at` becomes `now`
+/// when a value changes. Returns the user as it was before, `None` when no active user has
+/// that id. An email held by another user fails with [`AppError::Duplicate`] (unique email
+/// index).
+pub async fn update_by_id(
+    collection: &Collection<User>,
+    id: ObjectId,
+    set: Document,
+    now: DateTime,
+    comment: Option<Bson>,
+) -> Result<Option<User>, AppError> {
+    let _permit = db_permit().await;
+    let email = set.get_str("email").unwrap_or_default().to_owned();
+    match collection
+        .find_one_and_update(active(doc! { "_id": id }), set_pipeline(set, now.into()))
+        .with_options(
+            FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::Before)
+                .comment(comment)
+                .build(),
+        )
+        .await
+    {
+        Ok(user) => Ok(user),
+        Err(err) if is_duplicate_key(&err) => Err(AppError::Duplicate(format!(
+            "Email {} already exists",
+            email
+        ))),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Applies `set` to every user matching `filter`.
+pub async fn update_many(
+    collection: &Collection<User>,
+    filter: Document,
+    set: Document,
+    comment: Option<Bson>,
+) -> Result<UpdateResult, String> {
+    let _permit = db_permit().await;
+    match collection
+        .update_many(filter, set_pipeline(set, "$$NOW".into()))
+        .with_options(UpdateOptions::builder().comment(comment).build())
+        .await
+    {
+        Ok(res) => Ok(res),
+        Err(err) => {
+            error!("{}", err);
+            Err("Bulk update failed".into())
+        }
+    }
+}
+
+/// Removes every user document, soft-deleted ones included, returns how many were removed.
+pub async fn delete_all(collection: &Collection<User>) -> Result<u64, String> {
+    let _permit = db_permit().await;
+    match collection.delete_many(doc! {}).await {
+        Ok(res) => Ok(res.deleted_count),
+        Err(err) => {
+            error!("{}", err);
+            Err("Wipe failed".into())
+        }
+    }
+}
+
+/// Creates every index of the users registry that does not exist yet.
+///
+/// Indexes are matched by name, existing ones are left untouched.
+pub async fn ensure_indexes(
+    collection: &Collection<User>,
+    email_reuse_after_delete: bool,
+) -> Result<IndexesReport, String> {
+    let _permit = db_permit().await;
+    let existing = match collection.list_index_names().await {
+        Ok(names) => names,
+        Err(err) => match err.kind.as_ref() {
+            ErrorKind::Command(cmd) if cmd.code == NAMESPACE_NOT_FOUND => Vec::new(),
+            _ => {
+                error!("{}", err);
+                return Err("Failed to list users indexes".into());
+            }
+        },
+    };
+
+    // Indexes are never dropped here, the unique email index of the other mode would
+    // keep enforcing its rule
+    let stale = if email_reuse_after_delete {
+        EMAIL_UNIQUE_INDEX
+    } else {
+        EMAIL_UNIQUE_ACTIVE_INDEX
+    };
+    if existing.iter().any(|name| name == stale) {
+        warn!(
+            "Index {} on {} does not match EMAIL_REUSE_AFTER_DELETE={}, drop it manually",
+            stale,
+            collection.name(),
+            email_reuse_after_delete
+        );
+    }
+
+    let mut report = IndexesReport::default();
+    for index in users_indexes(email_reuse_after_delete) {
+        let name = index
+            .options
+            .as_ref()
+            .and_then(|opts| opts.name.clone())
+            .unwrap_or_default();
+
+        if existing.contains(&name) {
+            report.existing.push(name);
+            continue;
+        }
+
+        match collection.create_index(index).await {
+            Ok(_) => {
+                info!("Created index {} on {}", name, collection.name());
+                report.created.push(name);
+            }
+            Err(err) => {
+                error!("{}", err);
+                return Err(format!("Failed to create index {}", name));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Indexes of the collection, in server order, none when the collection does not exist.
+pub async fn list_indexes(collection: &Collection<User>) -> Result<Vec<IndexInfo>, String> {
+    let _permit = db_permit().await;
+    let mut cursor = match collection.list_indexes().await {
+        Ok(cursor) => cursor,
+        Err(err) => match err.kind.as_ref() {
+            ErrorKind::Command(cmd) if cmd.code == NAMESPACE_NOT_FOUND => return Ok(Vec::new()),
+            _ => {
+                error!("{}", err);
+                return Err("Failed to list users indexes".into());
+            }
+        },
+    };
+
+    let mut indexes = Vec::new();
+    while let Some(index) = cursor.next().await {
+        match index {
+            Ok(index) => indexes.push(IndexInfo::from(index)),
+            Err(err) => {
+                error!("{}", err);
+                return Err("Failed to list users indexes".into());
+            }
+        }
+    }
+    Ok(indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_pipeline_only_bumps_updated_at_on_changes() {
+        let pipeline = set_pipeline(doc! { "first_name": "$Ada", "age": 36 }, "$$NOW".into());
+        assert_eq!(
+            pipeline,
+            vec![
+                doc! { "$set": { "updated_at": { "$cond": [
+                    { "$or": [
+                        { "$ne": ["$first_name", { "$literal": "$Ada" }] },
+                        { "$ne": ["$age", { "$literal": 36 }] },
+                    ] },
+                    "$$NOW",
+                    "$updated_at",
+                ] } } },
+                doc! { "$set": {
+                    "first_name": { "$literal": "$Ada" },
+                    "age": { "$literal": 36 },
+                } },
+            ]
+        );
+    }
+
+    #[test]
+    fn projection_keeps_the_fields_a_user_needs() {
+        assert_eq!(build_projection(&UsersFilterQuery::default()), None);
+        let query = UsersFilterQuery {
+            fields: Some("id,age,updated_at".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_projection(&query),
+            Some(doc! { "_id": 1, "email": 1, "age": 1, "updated_at": 1, "created_at": 1 })
+        );
+    }
+
+    #[test]
+    fn search_filter_escapes_the_term() {
+        let contains = |field: &str| doc! { field: { "$regex": r"a\.\*\(", "$options": "i" } };
+        assert_eq!(
+            build_search_filter("a.*("),
+            doc! {
+                "$or": [contains("first_name"), contains("last_name"), contains("email")],
+                DELETED_AT_FIELD: Bson::Null,
+            }
+        );
+    }
+
+    #[test]
+    fn filter_has_only_the_given_fields() {
+        assert_eq!(
+            build_filter(&UsersFilterQuery::default()),
+            doc! { DELETED_AT_FIELD: Bson::Null }
+        );
+        let everyone = UsersFilterQuery {
+            include_deleted: true,
+            ..Default::default()
+        };
+        assert_eq!(build_filter(&everyone), doc! {});
+
+        let query =
+            |email: Option<&str>, last_name: Option<&str>, min_age: Option<u8>| UsersFilterQuery {
+                email: email.map(str::to_owned),
+                last_name: last_name.map(str::to_owned),
+                min_age,
+                ..Default::default()
+            };
+        for (query, expected) in [
+            (
+                query(Some("a@x.com"), None, None),
+                doc! { "email": "a@x.com" },
+            ),
+            (
+                query(None, Some("Ielpo"), None),
+                doc! { "last_name": "Ielpo" },
+            ),
+            (query(None, None, Some(18)), doc! { "age": { "$gte": 18 } }),
+            (
+                query(Some("a@x.com"), Some("Ielpo"), None),
+                doc! { "last_name": "Ielpo", "email": "a@x.com" },
+            ),
+            (
+                query(Some("a@x.com"), None, Some(18)),
+                doc! { "email": "a@x.com", "age": { "$gte": 18 } },
+            ),
+            (
+                query(None, Some("Ielpo"), Some(18)),
+                doc! { "last_name": "Ielpo", "age": { "$gte": 18 } },
+            ),
+            (
+                query(Some("a@x.com"), Some("Ielpo"), Some(18)),
+                doc! { "last_name": "Ielpo", "email": "a@x.com", "age": { "$gte": 18 } },
+            ),
+        ] {
+            assert_eq!(build_filter(&query), active(expected));
         }
     }
 }
@@ -1,12 +1,348 @@
 //! Business logic layer for user operations.
 //!
 //! This service layer orchestrates business logic and delegates
-//! data access operations to the repository layer.
-use actix_web::web;
-use mongodb::Client;
+//! data access operations to the configured storage backend (see `users_store`).
+use std::sync::Arc;
 
-use crate::users::users_repository;
+use futures_util::stream::BoxStream;
+use mongodb::bson::{DateTime, Document, oid::ObjectId};
 
-pub async fn delete_by_id(client: web::Data<Client>, id: &str) -> Result<(), String> {
-    users_repository::delete_by_id(client, id).await
+use crate::{
+    shared::{
+        concurrency::single_flight::SingleFlight, config::settings::SortConfig, error::AppError,
+    },
+    users::{
+        dto::UsersFilterQuery,
+        users_model::{IMMUTABLE_FIELDS, IndexInfo, IndexesReport, ScoredUser, User, UserProfile},
+        users_store::{InsertFailure, Page, StoreScope, UpdateCounts, UserStore},
+    },
+};
+
+/// In-flight `find_by_id` lookups, keyed by `database/id`.
+pub type UserReads = SingleFlight<String, Result<Option<User>, String>>;
+
+/// Normalizes an email address before it is stored or looked up.
+///
+/// Emails are trimmed and lowercased, so `Foo@Bar.com ` and `foo@bar.com`
+/// always refer to the same user.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Normalizes a users filter (list, count, bulk update) like the stored values.
+pub fn normalize_filter(mut query: UsersFilterQuery) -> UsersFilterQuery {
+    query.email = query.email.as_deref().map(normalize_email);
+    query
+}
+
+/// Immutable-field guard: rejects update documents touching `IMMUTABLE_FIELDS`.
+///
+/// Dotted paths are checked by their root field (`_id.x` touches `_id`).
+pub fn check_update_fields(set: &Document) -> Result<(), String> {
+    match set.keys().find(|key| {
+        let root = key.split('.').next().unwrap_or(key);
+        IMMUTABLE_FIELDS.contains(&root)
+    }) {
+        Some(key) => Err(format!("Field {} cannot be updated", key)),
+        None => Ok(()),
+    }
+}
+
+pub async fn list(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    filter: &UsersFilterQuery,
+    sort: &SortConfig,
+    page: Page,
+) -> Result<Vec<User>, String> {
+    store.list(scope, filter, sort, page).await
+}
+
+/// Every user as a stream, see `UserStore::stream_all`.
+pub fn stream_all(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+) -> BoxStream<'static, Result<User, String>> {
+    store.stream_all(scope)
+}
+
+/// Counts the users matching `filter`, returns the count and whether it is exact.
+///
+/// - empty filter with `include_deleted`: `estimated_count`, O(1) from the collection
+///   metadata (approximate)
+/// - empty filter otherwise: soft-deleted users must be left out, counted like a filter
+/// - filter with `limit` set: the count stops after `limit` matches, a count equal
+///   to `limit` is a lower bound (approximate)
+/// - filter without `limit`: exact count, a full scan unless indexed
+pub async fn count(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    filter: &UsersFilterQuery,
+    limit: Option<u64>,
+) -> Result<(u64, bool), String> {
+    // the collection metadata counts soft-deleted users too
+    if filter.is_empty() && filter.include_deleted {
+        let count = store.estimated_count(scope).await?;
+        return Ok((count, false));
+    }
+
+    let count = store.count(scope, filter, limit).await?;
+    Ok((count, limit.is_none_or(|limit| count < limit)))
+}
+
+pub async fn text_search(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    query: &str,
+    limit: u64,
+) -> Result<Option<Vec<ScoredUser>>, String> {
+    store.text_search(scope, query, limit).await
+}
+
+pub async fn search(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    term: &str,
+    sort: &SortConfig,
+    page: Page,
+) -> Result<Vec<User>, String> {
+    store.search(scope, term, sort, page).await
+}
+
+/// Updates the user `id`, returns it as it was before (see `UserStore::update_by_id`).
+pub async fn update_by_id(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    id: ObjectId,
+    set: Document,
+    now: DateTime,
+) -> Result<Option<User>, AppError> {
+    store.update_by_id(scope, id, set, now).await
+}
+
+pub async fn update_many(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    filter: &UsersFilterQuery,
+    set: Document,
+) -> Result<UpdateCounts, String> {
+    store.update_many(scope, filter, set).await
+}
+
+/// Finds a user by id.
+///
+/// With `reads` set, concurrent lookups of the same id in the same database share one
+/// store query and all receive its result (or error). The shared query carries the
+/// `comment` of the request that started it.
+pub async fn find_by_id(
+    store: &Arc<dyn UserStore>,
+    scope: &StoreScope,
+    id: ObjectId,
+    reads: Option<&UserReads>,
+) -> Result<Option<User>, String> {
+    let Some(reads) = reads else {
+        return store.find_by_id(scope, id).await;
+    };
+
+    let key = format!("{}/{}", scope.database, id.to_hex());
+    let store = Arc::clone(store);
+    let scope = scope.clone();
+    reads
+        .run(key, async move { store.find_by_id(&scope, id).await })
+        .await
+}
+
+pub async fn find_by_email(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    email: &str,
+) -> Result<Option<User>, String> {
+    store.find_by_email(scope, email).await
+}
+
+pub async fn insert(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    user: User,
+) -> Result<ObjectId, AppError> {
+    store.insert(scope, user).await
+}
+
+pub async fn insert_many(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    users: Vec<User>,
+) -> Result<Vec<InsertFailure>, AppError> {
+    store.insert_many(scope, users).await
+}
+
+pub async fn replace_by_id(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    user: User,
+) -> Result<Option<User>, AppError> {
+    store.replace_by_id(scope, user).await
+}
+
+pub async fn find_profile(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    id: ObjectId,
+) -> Result<Option<UserProfile>, String> {
+    store.find_profile(scope, id).await
+}
+
+pub async fn find_raw_by_id(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    id: ObjectId,
+) -> Result<Option<Document>, String> {
+    store.find_raw_by_id(scope, id).await
+}
+
+pub async fn delete_by_id(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    id: ObjectId,
+) -> Result<(), AppError> {
+    store.delete_by_id(scope, id).await
+}
+
+pub async fn hard_delete_by_id(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    id: ObjectId,
+) -> Result<(), AppError> {
+    store.hard_delete_by_id(scope, id).await
+}
+
+pub async fn find_random(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+) -> Result<Option<User>, String> {
+    store.find_random(scope).await
+}
+
+pub async fn find_existing_ids(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    ids: &[ObjectId],
+) -> Result<Vec<ObjectId>, String> {
+    store.find_existing_ids(scope, ids).await
+}
+
+pub async fn delete_many_by_ids(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    ids: &[ObjectId],
+) -> Result<u64, String> {
+    store.delete_many_by_ids(scope, ids).await
+}
+
+pub async fn delete_all(store: &dyn UserStore, scope: &StoreScope) -> Result<u64, String> {
+    store.delete_all(scope).await
+}
+
+/// Names combined by [`demo_users`].
+const DEMO_FIRST_NAMES: [&str; 8] = [
+    "Ada", "Alan", "Grace", "Linus", "Barbara", "Dennis", "Margaret", "Ken",
+];
+const DEMO_LAST_NAMES: [&str; 8] = [
+    "Lovelace", "Turing", "Hopper", "Torvalds", "Liskov", "Ritchie", "Hamilton", "Thompson",
+];
+
+/// Outcome of [`seed`].
+#[derive(Debug)]
+pub struct SeedReport {
+    pub inserted: usize,
+    /// Demo users not stored, typically already inserted by a previous run.
+    pub skipped: usize,
+}
+
+/// `count` sample users for local setups, `demo{n}@example.com` with n from 1.
+///
+/// Emails are stable across calls, so seeding twice doesn't duplicate users.
+pub fn demo_users(count: usize) -> Vec<User> {
+    let now = DateTime::now();
+    (1..=count)
+        .map(|n| User {
+            _id: ObjectId::new(),
+            first_name: DEMO_FIRST_NAMES[n % DEMO_FIRST_NAMES.len()].into(),
+            last_name: DEMO_LAST_NAMES[n / DEMO_FIRST_NAMES.len() % DEMO_LAST_NAMES.len()].into(),
+            email: format!("demo{}@example.com", n),
+            // every fifth user without age, the others between 18 and 77
+            age: (n % 5 != 0).then(|| 18 + (n * 7 % 60) as u8),
+            created_at: Some(now),
+            updated_at: Some(now),
+            deleted_at: None,
+        })
+        .collect()
+}
+
+/// Inserts [`demo_users`], those whose email is taken are skipped. Any other failed insert
+/// fails the seeding, the users stored meanwhile are kept.
+pub async fn seed(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+    count: usize,
+) -> Result<SeedReport, String> {
+    let failed = store
+        .insert_many(scope, demo_users(count))
+        .await
+        .map_err(|err| err.to_string())?;
+    let errors = failed.iter().filter(|failure| !failure.duplicate).count();
+    if errors > 0 {
+        return Err(format!(
+            "Failed to insert {} of {} demo users",
+            errors, count
+        ));
+    }
+    Ok(SeedReport {
+        inserted: count - failed.len(),
+        skipped: failed.len(),
+    })
+}
+
+pub async fn ensure_indexes(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+) -> Result<IndexesReport, String> {
+    store.ensure_indexes(scope).await
+}
+
+pub async fn list_indexes(
+    store: &dyn UserStore,
+    scope: &StoreScope,
+) -> Result<Vec<IndexInfo>, String> {
+    store.list_indexes(scope).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::users::users_memory_store::MemoryUserStore;
+
+    #[actix_web::test]
+    async fn seeding_twice_skips_the_existing_users() {
+        let store = MemoryUserStore::new(false);
+        let scope = StoreScope {
+            database: "test".into(),
+            comment: None,
+        };
+
+        let report = seed(&store, &scope, 12).await.unwrap();
+        assert_eq!((report.inserted, report.skipped), (12, 0));
+        let report = seed(&store, &scope, 15).await.unwrap();
+        assert_eq!((report.inserted, report.skipped), (3, 12));
+
+        let users = demo_users(10);
+        assert_eq!(users[0].email, "demo1@example.com");
+        assert_eq!(users[4].age, None);
+        assert!(
+            users
+                .iter()
+                .filter_map(|user| user.age)
+                .all(|age| (18..78).contains(&age))
+        );
+    }
 }
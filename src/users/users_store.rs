@@ -0,0 +1,572 @@
+//! Storage backends of the users registry.
+//!
+//! Services and handlers reach user data through [`UserStore`] only, shared as
+//! `web::Data<dyn UserStore>`. The backend is picked once at startup from `STORAGE_BACKEND`:
+//! - [`MongoUserStore`] (default): MongoDB, through `users_repository`
+//! - [`MemoryUserStore`](crate::users::users_memory_store::MemoryUserStore): process memory,
+//!   for tests and demos without a database
+use futures_util::{
+    future::{BoxFuture, FutureExt},
+    stream::BoxStream,
+};
+use mongodb::{
+    Client, Collection,
+    bson::{Bson, DateTime, Document, oid::ObjectId},
+};
+
+use crate::{
+    shared::{config::settings::SortConfig, error::AppError},
+    users::{
+        dto::UsersFilterQuery,
+        users_model::{IndexInfo, IndexesReport, ScoredUser, User, UserProfile},
+        users_repository,
+    },
+};
+
+/// Target of a store operation.
+#[derive(Debug, Clone)]
+pub struct StoreScope {
+    /// Database name, may come from the request (see `Settings::database_for`).
+    pub database: String,
+    /// MongoDB operation comment (see `users_repository::query_comment`), ignored by
+    /// other backends.
+    pub comment: Option<Bson>,
+}
+
+/// Slice of a list query, applied after filtering and sorting.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Page {
+    /// Cursor: only users whose `_id` is greater, then sorted by `_id` whatever the sort.
+    pub after: Option<ObjectId>,
+    pub skip: u64,
+    /// Maximum number of users returned, `None` returns all the remaining ones.
+    pub limit: Option<u64>,
+}
+
+/// A user `insert_many` did not store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertFailure {
+    /// Position of the user in the inserted ones.
+    pub index: usize,
+    /// Its email or id is already taken, otherwise the write failed (logged by the store).
+    pub duplicate: bool,
+}
+
+impl InsertFailure {
+    /// Message answered to the client for this user.
+    pub fn message(&self) -> &'static str {
+        if self.duplicate {
+            "Already exists"
+        } else {
+            "Failed to insert user"
+        }
+    }
+}
+
+/// Outcome of an update, `modified` leaves out matched users already holding the values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateCounts {
+    pub matched: u64,
+    pub modified: u64,
+}
+
+/// Users persistence contract, implemented by every storage backend.
+///
+/// Methods return boxed futures so the store can be used as a trait object. Errors are
+/// messages meant for the log, handlers answer with their own message.
+///
+/// Soft-deleted users (see `users_model::DELETED_AT_FIELD`) are left out by every method
+/// except `list`/`count` with `include_deleted`, `estimated_count`, `find_raw_by_id`,
+/// `hard_delete_by_id` and `delete_all`.
+pub trait UserStore: Send + Sync {
+    /// Users matching `filter`, ordered by `sort` (then `_id`) and sliced by `page`.
+    ///
+    /// A backend may read only the `filter.fields` selection, the other fields of the
+    /// returned users are then defaults.
+    fn list<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        sort: &'a SortConfig,
+        page: Page,
+    ) -> BoxFuture<'a, Result<Vec<User>, String>>;
+
+    /// Every user ordered by `_id`, yielded as they are read so memory stays bounded.
+    ///
+    /// The stream owns what it needs and may outlive the request handler.
+    fn stream_all(&self, scope: &StoreScope) -> BoxStream<'static, Result<User, String>>;
+
+    /// Exact number of users matching `filter`, stops at `limit` matches when set.
+    fn count<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        limit: Option<u64>,
+    ) -> BoxFuture<'a, Result<u64, String>>;
+
+    /// Users whose names match the words of `query`, most relevant first, at most `limit`.
+    ///
+    /// `None` when the storage can't search text (MongoDB collection without text index).
+    fn text_search<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        query: &'a str,
+        limit: u64,
+    ) -> BoxFuture<'a, Result<Option<Vec<ScoredUser>>, String>>;
+
+    /// Users whose `first_name`, `last_name` or `email` contains `term`, case-insensitively,
+    /// ordered by `sort` (then `_id`) and sliced by `page`.
+    ///
+    /// `term` is matched literally, regex metacharacters have no special meaning.
+    fn search<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        term: &'a str,
+        sort: &'a SortConfig,
+        page: Page,
+    ) -> BoxFuture<'a, Result<Vec<User>, String>>;
+
+    /// Total number of users, soft-deleted ones included, cheap but possibly approximate.
+    fn estimated_count<'a>(&'a self, scope: &'a StoreScope) -> BoxFuture<'a, Result<u64, String>>;
+
+    fn find_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<User>, String>>;
+
+    /// `email` must already be normalized.
+    fn find_by_email<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        email: &'a str,
+    ) -> BoxFuture<'a, Result<Option<User>, String>>;
+
+    /// User with its computed fields (see `users_repository::find_profile`).
+    fn find_profile<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<UserProfile>, String>>;
+
+    /// Stored user as a document, including the fields `User` does not map.
+    fn find_raw_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<Document>, String>>;
+
+    fn find_random<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<Option<User>, String>>;
+
+    /// Which of `ids` belong to an active user.
+    fn find_existing_ids<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        ids: &'a [ObjectId],
+    ) -> BoxFuture<'a, Result<Vec<ObjectId>, String>>;
+
+    /// Stores a new user, fails with `AppError::Duplicate` when its email is already taken.
+    fn insert<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        user: User,
+    ) -> BoxFuture<'a, Result<ObjectId, AppError>>;
+
+    /// Stores new users, each one even when others fail. Returns every user not stored.
+    fn insert_many<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        users: Vec<User>,
+    ) -> BoxFuture<'a, Result<Vec<InsertFailure>, AppError>>;
+
+    /// Replaces the user `user._id` as a whole but its stored `created_at`, `None` when it
+    /// does not exist. Fails with `AppError::Duplicate` when the email belongs to another user.
+    fn replace_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        user: User,
+    ) -> BoxFuture<'a, Result<Option<User>, AppError>>;
+
+    /// Sets the fields of `set` on the active user `id` in one write, and `updated_at` to
+    /// `now` when a value changes. Returns the user as it was before, `None` when no active
+    /// user has that id. An email held by another user fails with `AppError::Duplicate`.
+    fn update_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+        set: Document,
+        now: DateTime,
+    ) -> BoxFuture<'a, Result<Option<User>, AppError>>;
+
+    /// Sets the fields of `set` on every user matching `filter`, and `updated_at` on the
+    /// users whose values change.
+    fn update_many<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        set: Document,
+    ) -> BoxFuture<'a, Result<UpdateCounts, String>>;
+
+    /// Soft-deletes the user `id`, a missing or already deleted user fails with
+    /// `AppError::NotFound`.
+    fn delete_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<(), AppError>>;
+
+    /// Removes the user `id` for good, soft-deleted or not. A missing user fails with
+    /// `AppError::NotFound`.
+    fn hard_delete_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<(), AppError>>;
+
+    /// Soft-deletes the listed users, returns how many were active.
+    fn delete_many_by_ids<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        ids: &'a [ObjectId],
+    ) -> BoxFuture<'a, Result<u64, String>>;
+
+    /// Removes every user, soft-deleted ones included, returns how many existed.
+    fn delete_all<'a>(&'a self, scope: &'a StoreScope) -> BoxFuture<'a, Result<u64, String>>;
+
+    /// Prepares the storage (e.g. missing indexes), run at startup and by `POST /admin/reindex`.
+    fn ensure_indexes<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<IndexesReport, String>>;
+
+    /// Indexes of the storage, run by `GET /users/_indexes`.
+    fn list_indexes<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<Vec<IndexInfo>, String>>;
+
+    /// Checks the backend is reachable, run by `GET /health/ready`.
+    fn ping(&self) -> BoxFuture<'_, Result<(), String>>;
+}
+
+/// MongoDB backend, the users collection lives in the database of each scope.
+pub struct MongoUserStore {
+    client: Client,
+    collection: String,
+    email_reuse_after_delete: bool,
+    transactions: bool,
+}
+
+impl MongoUserStore {
+    /// `collection` is the users collection name (see `Settings::users_collection`),
+    /// `email_reuse_after_delete` picks the unique email index (see `users_model::users_indexes`),
+    /// `transactions` runs multi-step writes in transactions (see `Settings::mongodb_transactions`).
+    pub fn new(
+        client: Client,
+        collection: String,
+        email_reuse_after_delete: bool,
+        transactions: bool,
+    ) -> Self {
+        MongoUserStore {
+            client,
+            collection,
+            email_reuse_after_delete,
+            transactions,
+        }
+    }
+
+    fn collection(&self, scope: &StoreScope) -> Collection<User> {
+        users_repository::collection(&self.client, &scope.database, &self.collection)
+    }
+}
+
+impl UserStore for MongoUserStore {
+    fn list<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        sort: &'a SortConfig,
+        page: Page,
+    ) -> BoxFuture<'a, Result<Vec<User>, String>> {
+        let collection = self.collection(scope);
+        let projection = users_repository::build_projection(filter);
+        let filter = users_repository::build_filter(filter);
+        async move {
+            users_repository::find_all(
+                &collection,
+                filter,
+                sort.to_document(),
+                page,
+                projection,
+                scope.comment.clone(),
+            )
+            .await
+        }
+        .boxed()
+    }
+
+    fn stream_all(&self, scope: &StoreScope) -> BoxStream<'static, Result<User, String>> {
+        users_repository::stream_all(self.collection(scope), scope.comment.clone())
+    }
+
+    fn count<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        limit: Option<u64>,
+    ) -> BoxFuture<'a, Result<u64, String>> {
+        let collection = self.collection(scope);
+        let filter = users_repository::build_filter(filter);
+        async move {
+            users_repository::count(&collection, filter, limit, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn text_search<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        query: &'a str,
+        limit: u64,
+    ) -> BoxFuture<'a, Result<Option<Vec<ScoredUser>>, String>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::text_search(&collection, query, limit, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn search<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        term: &'a str,
+        sort: &'a SortConfig,
+        page: Page,
+    ) -> BoxFuture<'a, Result<Vec<User>, String>> {
+        let collection = self.collection(scope);
+        let filter = users_repository::build_search_filter(term);
+        async move {
+            users_repository::find_all(
+                &collection,
+                filter,
+                sort.to_document(),
+                page,
+                None,
+                scope.comment.clone(),
+            )
+            .await
+        }
+        .boxed()
+    }
+
+    fn estimated_count<'a>(&'a self, scope: &'a StoreScope) -> BoxFuture<'a, Result<u64, String>> {
+        let collection = self.collection(scope);
+        async move { users_repository::estimated_count(&collection, scope.comment.clone()).await }
+            .boxed()
+    }
+
+    fn find_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<User>, String>> {
+        let collection = self.collection(scope);
+        async move { users_repository::find_by_id(&collection, id, scope.comment.clone()).await }
+            .boxed()
+    }
+
+    fn find_by_email<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        email: &'a str,
+    ) -> BoxFuture<'a, Result<Option<User>, String>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::find_by_email(&collection, email, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn find_profile<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<UserProfile>, String>> {
+        let collection = self.collection(scope);
+        async move { users_repository::find_profile(&collection, id, scope.comment.clone()).await }
+            .boxed()
+    }
+
+    fn find_raw_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<Option<Document>, String>> {
+        let collection = self.collection(scope);
+        async move { users_repository::find_raw_by_id(&collection, id).await }.boxed()
+    }
+
+    fn find_random<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<Option<User>, String>> {
+        let collection = self.collection(scope);
+        async move { users_repository::find_random(&collection, scope.comment.clone()).await }
+            .boxed()
+    }
+
+    fn find_existing_ids<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        ids: &'a [ObjectId],
+    ) -> BoxFuture<'a, Result<Vec<ObjectId>, String>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::find_existing_ids(&collection, ids, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn insert<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        user: User,
+    ) -> BoxFuture<'a, Result<ObjectId, AppError>> {
+        let collection = self.collection(scope);
+        async move {
+            if self.transactions {
+                users_repository::insert_in_transaction(
+                    &self.client,
+                    &collection,
+                    user,
+                    scope.comment.clone(),
+                )
+                .await
+            } else {
+                users_repository::insert(&collection, user, scope.comment.clone()).await
+            }
+        }
+        .boxed()
+    }
+
+    fn insert_many<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        users: Vec<User>,
+    ) -> BoxFuture<'a, Result<Vec<InsertFailure>, AppError>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::insert_many(&collection, users, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn replace_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        user: User,
+    ) -> BoxFuture<'a, Result<Option<User>, AppError>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::replace_by_id(&collection, user, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn update_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+        set: Document,
+        now: DateTime,
+    ) -> BoxFuture<'a, Result<Option<User>, AppError>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::update_by_id(&collection, id, set, now, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn update_many<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        filter: &'a UsersFilterQuery,
+        set: Document,
+    ) -> BoxFuture<'a, Result<UpdateCounts, String>> {
+        let collection = self.collection(scope);
+        let filter = users_repository::build_filter(filter);
+        async move {
+            users_repository::update_many(&collection, filter, set, scope.comment.clone())
+                .await
+                .map(|res| UpdateCounts {
+                    matched: res.matched_count,
+                    modified: res.modified_count,
+                })
+        }
+        .boxed()
+    }
+
+    fn delete_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        let collection = self.collection(scope);
+        async move { users_repository::delete_by_id(&collection, id, scope.comment.clone()).await }
+            .boxed()
+    }
+
+    fn hard_delete_by_id<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        id: ObjectId,
+    ) -> BoxFuture<'a, Result<(), AppError>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::hard_delete_by_id(&collection, id, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn delete_many_by_ids<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+        ids: &'a [ObjectId],
+    ) -> BoxFuture<'a, Result<u64, String>> {
+        let collection = self.collection(scope);
+        async move {
+            users_repository::delete_many_by_ids(&collection, ids, scope.comment.clone()).await
+        }
+        .boxed()
+    }
+
+    fn delete_all<'a>(&'a self, scope: &'a StoreScope) -> BoxFuture<'a, Result<u64, String>> {
+        let collection = self.collection(scope);
+        async move { users_repository::delete_all(&collection).await }.boxed()
+    }
+
+    fn ensure_indexes<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<IndexesReport, String>> {
+        let collection = self.collection(scope);
+        let email_reuse_after_delete = self.email_reuse_after_delete;
+        async move { users_repository::ensure_indexes(&collection, email_reuse_after_delete).await }
+            .boxed()
+    }
+
+    fn list_indexes<'a>(
+        &'a self,
+        scope: &'a StoreScope,
+    ) -> BoxFuture<'a, Result<Vec<IndexInfo>, String>> {
+        let collection = self.collection(scope);
+        async move { users_repository::list_indexes(&collection).await }.boxed()
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), String>> {
+        users_repository::ping(&self.client).boxed()
+    }
+}
@@ -0,0 +1,69 @@
+use serde::Serialize;
+
+use crate::shared::config::settings::{RateLimitBackend, StorageBackend};
+
+/// Body of `GET /version`.
+#[derive(Serialize, Debug)]
+pub struct VersionDtoResponse {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub build: BuildInfo,
+    /// Only answered to callers presenting the `ADMIN_TOKEN`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeFlags>,
+}
+
+/// Capabilities fixed when the binary was compiled.
+#[derive(Serialize, Debug)]
+pub struct BuildInfo {
+    /// `debug` or `release`
+    pub profile: &'static str,
+    pub target_os: &'static str,
+    pub target_arch: &'static str,
+    /// Cargo features enabled at build time.
+    pub features: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        // Every optional Cargo feature is listed here with a `cfg!` check, e.g.
+        // `("metrics", cfg!(feature = "metrics"))`, so `/version` tells which ones are built in
+        let features: [(&'static str, bool); 0] = [];
+
+        BuildInfo {
+            profile: if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            },
+            target_os: std::env::consts::OS,
+            target_arch: std::env::consts::ARCH,
+            features: features
+                .into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+}
+
+/// Optional behaviours active in this process, resolved from the environment.
+#[derive(Serialize, Debug)]
+pub struct RuntimeFlags {
+    pub storage: StorageBackend,
+    pub dev_mode: bool,
+    pub web_ui: bool,
+    /// OpenAPI document and Swagger UI are served.
+    pub api_docs: bool,
+    /// `ADMIN_TOKEN` is set.
+    pub admin: bool,
+    /// `JWT_SECRET` is set, `/users` requires a bearer JWT.
+    pub jwt_auth: bool,
+    pub destructive_ops: bool,
+    pub maintenance: bool,
+    pub rate_limit: RateLimitBackend,
+    pub coalesce_reads: bool,
+    pub mongodb_query_comment: bool,
+    pub redis_keepalive: bool,
+    pub test_db_header: bool,
+}
@@ -0,0 +1,90 @@
+use crate::{
+    shared::{
+        auth::admin::{bearer_token, is_admin_token},
+        config::settings::Settings,
+        dto::response::http_ok,
+        middleware::{maintenance::MaintenanceMode, timeout::timeout},
+    },
+    version::dto::{BuildInfo, RuntimeFlags, VersionDtoResponse},
+};
+use actix_web::{HttpRequest, HttpResponse, get, middleware::from_fn, web};
+
+/// REST API controller for build and runtime information.
+///
+/// All routes are prefixed with `/version` as specified in main.rs via `web::scope("/version")`.
+///
+/// # Routes
+/// - `GET /version` - Package version and compiled Cargo features, plus the active runtime
+///   flags for callers presenting the `ADMIN_TOKEN`
+
+#[get("", wrap = "from_fn(timeout)")]
+async fn version(
+    req: HttpRequest,
+    config: web::Data<Settings>,
+    maintenance: web::Data<MaintenanceMode>,
+) -> HttpResponse {
+    // the flags tell an attacker which risky switches are on, only admins see them
+    let admin = config
+        .admin_token
+        .as_deref()
+        .zip(bearer_token(req.headers()))
+        .is_some_and(|(expected, token)| is_admin_token(token, expected));
+    http_ok(VersionDtoResponse {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        build: BuildInfo::current(),
+        runtime: admin.then(|| RuntimeFlags {
+            storage: config.storage_backend,
+            dev_mode: config.dev_mode,
+            web_ui: config.enable_web_ui,
+            api_docs: config.enable_api_docs,
+            admin: config.admin_token.is_some(),
+            jwt_auth: config.jwt_secret.is_some(),
+            destructive_ops: config.destructive_ops,
+            maintenance: maintenance.is_enabled(),
+            rate_limit: config.rate_limit.backend,
+            coalesce_reads: config.coalesce_reads,
+            mongodb_query_comment: config.mongodb_query_comment,
+            redis_keepalive: config.redis_keepalive_secs > 0,
+            test_db_header: config.test_db_header,
+        }),
+    })
+}
+
+/// Service configuration for version routes.
+///
+/// Registers all version endpoint handlers with the Actix-web application.
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(version);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, test};
+
+    #[actix_web::test]
+    async fn runtime_flags_are_reserved_to_admins() {
+        let mut config = Settings::defaults();
+        config.admin_token = Some("admin-token".into());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(MaintenanceMode::new(false, 60)))
+                .service(web::scope("/version").configure(super::config)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/version").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body.get("runtime").is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/version")
+            .insert_header(("Authorization", "Bearer admin-token"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["runtime"]["dev_mode"], false);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{
+    health::dto::HealthDtoResponse,
+    shared::{
+        config::settings::Settings,
+        dto::response::{http_not_found, http_ok},
+        middleware::timeout::timeout,
+    },
+};
+use actix_files::NamedFile;
+use actix_web::{
+    HttpRequest, HttpResponse, get,
+    http::header::{self, HeaderValue},
+    middleware::from_fn,
+    web,
+};
+use log::debug;
+use std::path::Path;
+
+/// Browsers cache the favicon for a day.
+const FAVICON_CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// REST API controller for the standard paths browsers and tools probe.
+///
+/// The `/.well-known` routes (RFC 8615) are prefixed as specified in main.rs via
+/// `web::scope("/.well-known")`, `/favicon.ico` is registered at the root with
+/// [`favicon_config`]. Missing files answer a plain 404, never logged as errors.
+///
+/// # Routes
+/// - `GET /favicon.ico` - The `FAVICON_PATH` file, empty 404 when it does not exist
+/// - `GET /.well-known/health` - 200 while the process is running, like `/health/live`
+/// - `GET /.well-known/{name}` - The `name` file of `WELL_KNOWN_DIR` (e.g. `security.txt`),
+///   404 when not configured
+
+#[get("/favicon.ico", wrap = "from_fn(timeout)")]
+async fn favicon(req: HttpRequest, config: web::Data<Settings>) -> HttpResponse {
+    match NamedFile::open_async(&config.favicon_path).await {
+        Ok(file) => {
+            let mut response = file.into_response(&req);
+            response.headers_mut().insert(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(FAVICON_CACHE_CONTROL),
+            );
+            response
+        }
+        Err(err) => {
+            debug!("No favicon at {}: {}", config.favicon_path, err);
+            HttpResponse::NotFound().finish()
+        }
+    }
+}
+
+#[get("health", wrap = "from_fn(timeout)")]
+async fn health() -> HttpResponse {
+    http_ok(HealthDtoResponse {
+        status: "ok".into(),
+        dependencies: None,
+    })
+}
+
+#[get("{name}", wrap = "from_fn(timeout)")]
+async fn well_known_file(
+    req: HttpRequest,
+    config: web::Data<Settings>,
+    name: web::Path<String>,
+) -> HttpResponse {
+    let name = name.into_inner();
+    // One plain file name, hidden files and `..` are never served
+    if name.starts_with('.') || name.contains(['/', '\\']) {
+        return http_not_found(format!("Unknown well-known path {}", name));
+    }
+
+    let path = Path::new(&config.well_known_dir).join(&name);
+    match NamedFile::open_async(&path).await {
+        Ok(file) if file.metadata().is_file() => file.into_response(&req),
+        Ok(_) => http_not_found(format!("Unknown well-known path {}", name)),
+        Err(err) => {
+            debug!("No well-known file {}: {}", path.display(), err);
+            http_not_found(format!("Unknown well-known path {}", name))
+        }
+    }
+}
+
+/// Service configuration for `/favicon.ico`, registered at the application root.
+pub fn favicon_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(favicon);
+}
+
+/// Service configuration for well-known routes.
+///
+/// Registers all `/.well-known` handlers with the Actix-web application.
+pub fn config(cfg: &mut web::ServiceConfig) {
+    // literal paths must be registered before `{name}`
+    cfg.service(health);
+    cfg.service(well_known_file);
+}
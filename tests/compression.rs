@@ -0,0 +1,83 @@
+//! Responses are compressed per `Accept-Encoding`, conditional requests keep working.
+use std::sync::Arc;
+
+use actix_files::Files;
+use actix_web::{
+    App,
+    http::{StatusCode, header},
+    middleware::Compress,
+    test, web,
+};
+use serde_json::json;
+
+use rust_web_starter::{
+    shared::config::settings::Settings,
+    users::{
+        dto::UserIdDtoResponse, users_controller, users_memory_store::MemoryUserStore,
+        users_service::UserReads, users_store::UserStore,
+    },
+};
+
+#[actix_web::test]
+async fn compressed_users_keep_their_etag() {
+    let store: Arc<dyn UserStore> = Arc::new(MemoryUserStore::new(false));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(store))
+            .app_data(web::Data::new(Settings::defaults()))
+            .app_data(web::Data::new(UserReads::new()))
+            .wrap(Compress::default())
+            .service(web::scope("/users").configure(users_controller::config)),
+    )
+    .await;
+    let req = test::TestRequest::post().uri("/users").set_json(
+        json!({ "first_name": "Ada", "last_name": "Lovelace", "email": "ada@example.com" }),
+    );
+    let created: UserIdDtoResponse =
+        test::read_body_json(test::call_service(&app, req.to_request()).await).await;
+    let uri = format!("/users/{}", created.id);
+
+    let req = test::TestRequest::get()
+        .uri(&uri)
+        .insert_header((header::ACCEPT_ENCODING, "gzip"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+    assert_eq!(res.headers().get(header::VARY).unwrap(), "accept-encoding");
+    let etag = res.headers().get(header::ETAG).unwrap().clone();
+
+    // the weak tag matches whatever the encoding, the 304 has no body to compress
+    let req = test::TestRequest::get()
+        .uri(&uri)
+        .insert_header((header::ACCEPT_ENCODING, "br"))
+        .insert_header((header::IF_NONE_MATCH, etag.clone()))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers().get(header::ETAG), Some(&etag));
+    assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(res.headers().get(header::ETAG), Some(&etag));
+}
+
+#[actix_web::test]
+async fn static_assets_are_compressed() {
+    let app = test::init_service(
+        App::new()
+            .wrap(Compress::default())
+            .service(Files::new("/assets", "./assets")),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/assets/css/style.css")
+        .insert_header((header::ACCEPT_ENCODING, "br, gzip"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get(header::CONTENT_ENCODING).unwrap(), "br");
+}
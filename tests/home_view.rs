@@ -0,0 +1,40 @@
+//! The home page template, `GET /` renders it with Handlebars from `HomeData`.
+use rust_web_starter::{
+    home::dto::HomeData,
+    shared::config::settings::{Settings, build_handlebars},
+};
+use serde_json::json;
+
+#[test]
+fn template_fields_match_home_data() {
+    let mut handlebars = build_handlebars(&Settings::defaults(), |_| {});
+    // strict: a field the data does not provide is an error instead of a blank
+    handlebars.set_strict_mode(true);
+    let data = HomeData {
+        first_hit: "2024-03-05T21:07:09.000000000Z".into(),
+        title: "Rust web starter".into(),
+        visits: Some(3),
+    };
+    let html = handlebars.render("home", &data).unwrap();
+    assert!(html.contains("<h1>Rust web starter</h1>"));
+    assert!(html.contains("2024-03-05T21:07:09.000000000Z"));
+    assert!(html.contains("You visited this page 3 time(s)"));
+
+    assert!(
+        handlebars
+            .render("home", &json!({ "first_hit": data.first_hit }))
+            .is_err()
+    );
+
+    // without Redis there is no session to count visits in
+    let html = handlebars
+        .render(
+            "home",
+            &HomeData {
+                visits: None,
+                ..data
+            },
+        )
+        .unwrap();
+    assert!(!html.contains("You visited"));
+}
@@ -0,0 +1,23 @@
+//! The user page template, `GET /users/{id}/view` renders it with Handlebars.
+use rust_web_starter::shared::config::settings::{Settings, build_handlebars};
+use serde_json::json;
+
+#[test]
+fn user_supplied_markup_is_escaped() {
+    let handlebars = build_handlebars(&Settings::defaults(), |_| {});
+    let html = handlebars
+        .render(
+            "user",
+            &json!({
+                "first_name": "<script>alert(1)</script>",
+                "last_name": "O'Brien & <b>Co</b>",
+                "email": "ada@example.com",
+            }),
+        )
+        .unwrap();
+
+    assert!(!html.contains("<script>alert(1)</script>"));
+    assert!(!html.contains("<b>"));
+    assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(html.contains("O&#x27;Brien &amp; &lt;b&gt;Co&lt;/b&gt;"));
+}
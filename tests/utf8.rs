@@ -0,0 +1,82 @@
+//! Non-ASCII user data round-trips through JSON, BSON and templates.
+use actix_web::{App, HttpResponse, http::header, middleware::from_fn, test, web};
+use mongodb::bson::{self, doc, oid::ObjectId};
+use serde_json::{Value, json};
+
+use rust_web_starter::{
+    shared::{
+        config::settings::{Settings, build_handlebars},
+        middleware::charset::utf8_charset,
+    },
+    users::{
+        dto::{CreateUserDtoRequest, UserDtoResponse},
+        users_model::User,
+    },
+};
+
+const FIRST_NAME: &str = "José 李";
+
+#[actix_web::test]
+async fn names_round_trip_unchanged() {
+    let body =
+        json!({ "first_name": FIRST_NAME, "last_name": "Müller", "email": "jose@example.com" });
+    let dto: CreateUserDtoRequest = serde_json::from_str(&body.to_string()).unwrap();
+
+    // stored and read back as MongoDB does, through the BSON bytes
+    let stored = bson::to_vec(&doc! {
+        "_id": ObjectId::new(),
+        "first_name": dto.first_name,
+        "last_name": dto.last_name,
+        "email": dto.email,
+    })
+    .unwrap();
+    let user: User = bson::from_slice(&stored).unwrap();
+
+    let response = serde_json::to_string(&UserDtoResponse::from(user)).unwrap();
+    let user: Value = serde_json::from_str(&response).unwrap();
+    assert_eq!(user["first_name"], FIRST_NAME);
+    assert_eq!(user["last_name"], "Müller");
+}
+
+#[actix_web::test]
+async fn names_render_unchanged() {
+    let html = build_handlebars(&Settings::defaults(), |_| {})
+        .render(
+            "user",
+            &json!({ "first_name": FIRST_NAME, "last_name": "Müller", "email": "jose@example.com" }),
+        )
+        .unwrap();
+    assert!(html.contains(&format!("<h1>{} Müller</h1>", FIRST_NAME)));
+}
+
+#[actix_web::test]
+async fn textual_responses_declare_utf8() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(utf8_charset))
+            .route(
+                "/json",
+                web::get().to(|| async { HttpResponse::Ok().json(json!({ "name": FIRST_NAME })) }),
+            )
+            .route(
+                "/html",
+                web::get().to(|| async {
+                    HttpResponse::Ok()
+                        .content_type("text/html")
+                        .body(FIRST_NAME)
+                }),
+            ),
+    )
+    .await;
+
+    for (uri, content_type) in [
+        ("/json", "application/json; charset=utf-8"),
+        ("/html", "text/html; charset=utf-8"),
+    ] {
+        let res = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            content_type
+        );
+    }
+}